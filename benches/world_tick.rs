@@ -0,0 +1,97 @@
+#![allow(unused_parens)]
+
+// Times World::tick on 50k node scenes.
+// Run with `cargo bench`.
+
+use std::time::Instant;
+
+use rope_lib::rope::*;
+use rope_lib::solver::SolverMode;
+
+const WIDTH: usize = 250;
+const HEIGHT: usize = 200;
+const TICKS: u32 = 100;
+
+fn add_grid(world: &mut World) {
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let id = world.add_node(x as f32 * 10.0, y as f32 * 10.0);
+            if (y == 0 && x % 10 == 0) {
+                world.set_node_type(id, NodeType::Fixed);
+            }
+        }
+    }
+}
+
+// Loose nodes falling towards an attractor, only integration and force evaluation
+fn build_particles() -> World {
+    let mut world = World::default();
+
+    // Sleeping would skip most of the work we want to time
+    world.sleep.threshold = 0.0;
+
+    add_grid(&mut world);
+
+    world.add_force(Box::new(ConstantForce {
+        force: Vec2::new(0.0, 0.2),
+    }));
+    world.add_force(Box::new(InverseSquareForce {
+        strength: 100.0,
+        pos: Vec2::new(1250.0, 3000.0),
+    }));
+
+    world
+}
+
+// Net hanging from its top edge, dominated by the rope solver
+fn build_net(mode: SolverMode) -> World {
+    let mut world = World::default();
+    world.sleep.threshold = 0.0;
+    world.solver.mode = mode;
+
+    add_grid(&mut world);
+
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let id = y * WIDTH + x;
+            if (x + 1 < WIDTH) {
+                world.add_rope(id, id + 1);
+            }
+            if (y + 1 < HEIGHT) {
+                world.add_rope(id, id + WIDTH);
+            }
+        }
+    }
+
+    world.add_force(Box::new(ConstantForce {
+        force: Vec2::new(0.0, 0.2),
+    }));
+
+    world
+}
+
+fn bench(name: &str, mut world: World) {
+    // Warm up, the first tick also builds the islands
+    world.tick(1.0);
+
+    let start = Instant::now();
+    for _ in 0..TICKS {
+        world.tick(1.0);
+    }
+    let elapsed = start.elapsed();
+
+    println!(
+        "{}: {} nodes, {} ropes, {:.3} ms/tick",
+        name,
+        world.nodes.len(),
+        world.ropes.len(),
+        elapsed.as_secs_f64() * 1000.0 / TICKS as f64
+    );
+}
+
+fn main() {
+    bench("particles", build_particles());
+    bench("net sequential", build_net(SolverMode::Sequential));
+    bench("net coloured", build_net(SolverMode::Coloured));
+    bench("net jacobi", build_net(SolverMode::Jacobi));
+}
@@ -0,0 +1,71 @@
+use std::f32::consts::PI;
+
+use crate::rope::*;
+
+/// Drives a rope's rest length round `base_length` on a sine wave, like a muscle.
+/// `period` is in ticks and `phase` in radians, so actuators on the same period
+/// with staggered phases make a travelling wave.
+#[derive(Debug, Clone, Copy)]
+pub struct Actuator {
+    pub rope: usize,
+    pub base_length: f32,
+    pub amplitude: f32,
+    pub period: f32,
+    pub phase: f32,
+}
+
+impl Actuator {
+    pub fn length_at(&self, ticks: u64) -> f32 {
+        let angle = 2.0 * PI * (ticks as f64 / self.period as f64).fract() as f32 + self.phase;
+        (self.base_length + self.amplitude * angle.sin()).max(Rope::MIN_LENGTH)
+    }
+}
+
+impl World {
+    /// Oscillates about the rope's current length.
+    pub fn add_actuator(&mut self, rope: usize, amplitude: f32, period: f32, phase: f32) -> usize {
+        debug_assert!(period > 0.0);
+
+        self.actuators.push(Actuator {
+            rope,
+            base_length: self.get_rope(rope).length(),
+            amplitude,
+            period,
+            phase,
+        });
+        self.wake_rope(rope);
+        self.actuators.len() - 1
+    }
+
+    pub fn get_actuator(&self, id: usize) -> &Actuator {
+        &self.actuators[id]
+    }
+
+    pub fn set_actuator(&mut self, id: usize, amplitude: f32, period: f32, phase: f32) {
+        debug_assert!(period > 0.0);
+
+        let actuator = &mut self.actuators[id];
+        actuator.amplitude = amplitude;
+        actuator.period = period;
+        actuator.phase = phase;
+        let rope = actuator.rope;
+        self.wake_rope(rope);
+    }
+
+    pub(crate) fn update_actuators(&mut self) {
+        for actuator in &self.actuators {
+            let rope = &mut self.ropes[actuator.rope];
+            if (!rope.broken) {
+                rope.length = actuator.length_at(self.ticks);
+            }
+        }
+    }
+
+    /// Islands with a working actuator never come to rest.
+    pub(crate) fn actuated_nodes(&self) -> impl Iterator<Item = usize> + '_ {
+        self.actuators
+            .iter()
+            .filter(move |a| a.amplitude != 0.0 && !self.ropes[a.rope].broken)
+            .map(move |a| self.ropes[a.rope].from)
+    }
+}
@@ -0,0 +1,221 @@
+use std::f32::consts::PI;
+
+use crate::chain::Chain;
+use crate::rope::*;
+
+/// Bends the corner `a`-`b`-`c` towards `rest_angle`, the signed angle at `b` from `a` round to `c`.
+/// Outside of `limits` the corner is pushed back to the nearest limit at full strength,
+/// so a hinge can be free inside a range or only bend one way.
+#[derive(Debug, Clone, Copy)]
+pub struct AngleConstraint {
+    pub a: usize,
+    pub b: usize,
+    pub c: usize,
+    pub rest_angle: f32,
+    /// Fraction of the way to the rest angle the corner moves per solver pass, zero to only enforce limits.
+    pub stiffness: f32,
+    pub limits: Option<(f32, f32)>,
+}
+
+impl World {
+    /// Uses the corner's current angle as its rest angle.
+    pub fn add_angle_constraint(&mut self, a: usize, b: usize, c: usize, stiffness: f32) -> usize {
+        debug_assert!(a != b && b != c && a != c);
+
+        let rest_angle = corner_angle(self.nodes.pos[a], self.nodes.pos[b], self.nodes.pos[c]);
+        self.angles.push(AngleConstraint {
+            a,
+            b,
+            c,
+            rest_angle,
+            stiffness: stiffness.clamp(0.0, 1.0),
+            limits: None,
+        });
+        self.mark_topology_dirty();
+        self.wake_angle(self.angles.len() - 1);
+        self.angles.len() - 1
+    }
+
+    pub fn get_angle_constraint(&self, id: usize) -> &AngleConstraint {
+        &self.angles[id]
+    }
+
+    pub fn set_angle_rest(&mut self, id: usize, rest_angle: f32) {
+        self.angles[id].rest_angle = wrap_angle(rest_angle);
+        self.wake_angle(id);
+    }
+
+    pub fn set_angle_limits(&mut self, id: usize, limits: Option<(f32, f32)>) {
+        self.angles[id].limits = limits;
+        self.wake_angle(id);
+    }
+
+    /// Stiffen every joint of a chain towards straight, zero to let it fold freely again.
+    pub fn set_chain_bending(&mut self, id: usize, stiffness: f32) {
+        self.chains[id].bending = stiffness.clamp(0.0, 1.0);
+        for &node in &self.chains[id].nodes {
+            self.islands.wake_node(node);
+        }
+    }
+
+    fn wake_angle(&mut self, id: usize) {
+        let angle = self.angles[id];
+        self.islands.wake_node(angle.a);
+        self.islands.wake_node(angle.b);
+        self.islands.wake_node(angle.c);
+    }
+}
+
+impl AngleConstraint {
+    /// Moves the three nodes along the gradient of the corner angle, split by their weights.
+    pub(crate) fn solve(&self, pos: &mut [Vec2], weights: &[f32]) {
+        let w_a = weights[self.a];
+        let w_b = weights[self.b];
+        let w_c = weights[self.c];
+        if (w_a == 0.0 && w_b == 0.0 && w_c == 0.0) {
+            return;
+        }
+
+        let u = pos[self.a].sub(pos[self.b]);
+        let v = pos[self.c].sub(pos[self.b]);
+        let u_mag2 = u.mag2();
+        let v_mag2 = v.mag2();
+        if (u_mag2 == 0.0 || v_mag2 == 0.0) {
+            return;
+        }
+
+        let angle = corner_angle(pos[self.a], pos[self.b], pos[self.c]);
+        let (target, strength) = match self.limits.and_then(|(min, max)| outside_limit(angle, min, max)) {
+            Some(limit) => (limit, 1.0),
+            None => (self.rest_angle, self.stiffness),
+        };
+
+        let error = wrap_angle(angle - target);
+        if (error == 0.0 || strength == 0.0) {
+            return;
+        }
+
+        // The angle turns by the perpendicular of each arm over its length squared
+        let grad_a = Vec2::new(u.y, -u.x).mult(1.0 / u_mag2);
+        let grad_c = Vec2::new(-v.y, v.x).mult(1.0 / v_mag2);
+        let grad_b = grad_a.add(grad_c).mult(-1.0);
+
+        let denom = w_a * grad_a.mag2() + w_b * grad_b.mag2() + w_c * grad_c.mag2();
+        if (denom == 0.0) {
+            return;
+        }
+
+        let s = -strength * error / denom;
+        pos[self.a] = pos[self.a].add(grad_a.mult(s * w_a));
+        pos[self.b] = pos[self.b].add(grad_b.mult(s * w_b));
+        pos[self.c] = pos[self.c].add(grad_c.mult(s * w_c));
+    }
+}
+
+/// Signed angle at `b` from `a` round to `c`, in -PI to PI.
+fn corner_angle(a: Vec2, b: Vec2, c: Vec2) -> f32 {
+    let u = a.sub(b);
+    let v = c.sub(b);
+    (u.x * v.y - u.y * v.x).atan2(u.dot(v))
+}
+
+fn wrap_angle(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(2.0 * PI) - PI
+}
+
+// The limit nearest to the angle if it's outside of the range going anticlockwise from min to max
+fn outside_limit(angle: f32, min: f32, max: f32) -> Option<f32> {
+    // A full turn or more allows every angle, rather than wrapping round to nothing
+    if (max - min >= 2.0 * PI) {
+        return None;
+    }
+
+    let span = (max - min).rem_euclid(2.0 * PI);
+    if ((angle - min).rem_euclid(2.0 * PI) <= span) {
+        return None;
+    }
+
+    if (wrap_angle(angle - min).abs() < wrap_angle(angle - max).abs()) {
+        Some(min)
+    } else {
+        Some(max)
+    }
+}
+
+/// Every angle constraint to solve this tick, the world's own plus the joints of chains with `bending` set.
+pub(crate) fn collect_angles(chains: &[Chain], ropes: &[Rope], angles: &[AngleConstraint], out: &mut Vec<AngleConstraint>) {
+    out.clear();
+    out.extend_from_slice(angles);
+
+    for chain in chains.iter().filter(|c| c.bending > 0.0) {
+        for i in 1..chain.ropes.len() {
+            // A broken link lets the chain fold there
+            if (ropes[chain.ropes[i - 1]].broken || ropes[chain.ropes[i]].broken) {
+                continue;
+            }
+
+            out.push(AngleConstraint {
+                a: chain.nodes[i - 1],
+                b: chain.nodes[i],
+                c: chain.nodes[i + 1],
+                rest_angle: PI,
+                stiffness: chain.bending,
+                limits: None,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inside_range() {
+        assert_eq!(outside_limit(0.0, -1.0, 1.0), None);
+        assert_eq!(outside_limit(-1.0, -1.0, 1.0), None);
+        assert_eq!(outside_limit(1.0, -1.0, 1.0), None);
+    }
+
+    #[test]
+    fn nearest_limit() {
+        assert_eq!(outside_limit(1.5, -1.0, 1.0), Some(1.0));
+        assert_eq!(outside_limit(-1.5, -1.0, 1.0), Some(-1.0));
+        // Just past PI is closer to -1 going round the back
+        assert_eq!(outside_limit(-3.0, -1.0, 1.0), Some(-1.0));
+        assert_eq!(outside_limit(3.0, -1.0, 1.0), Some(1.0));
+    }
+
+    #[test]
+    fn range_across_pi() {
+        // Anticlockwise from 3 through PI round to -3
+        assert_eq!(outside_limit(PI, 3.0, -3.0), None);
+        assert_eq!(outside_limit(-PI, 3.0, -3.0), None);
+        assert_eq!(outside_limit(3.1, 3.0, -3.0), None);
+        assert_eq!(outside_limit(-3.1, 3.0, -3.0), None);
+        assert_eq!(outside_limit(2.0, 3.0, -3.0), Some(3.0));
+        assert_eq!(outside_limit(-2.0, 3.0, -3.0), Some(-3.0));
+    }
+
+    #[test]
+    fn one_way_range() {
+        // Swapping the limits allows the other side instead
+        assert_eq!(outside_limit(0.0, 1.0, -1.0), Some(1.0));
+        assert_eq!(outside_limit(-0.5, 1.0, -1.0), Some(-1.0));
+        assert_eq!(outside_limit(2.0, 1.0, -1.0), None);
+    }
+
+    #[test]
+    fn full_turn() {
+        assert_eq!(outside_limit(0.0, -PI, PI), None);
+        assert_eq!(outside_limit(1.0, -PI, PI), None);
+        assert_eq!(outside_limit(-3.0, 0.0, 3.0 * PI), None);
+    }
+
+    #[test]
+    fn wraps_angles() {
+        assert!((wrap_angle(3.0 * PI / 2.0) + PI / 2.0).abs() < 1e-5);
+        assert!((wrap_angle(-3.0 * PI / 2.0) - PI / 2.0).abs() < 1e-5);
+        assert_eq!(wrap_angle(0.5), 0.5);
+    }
+}
@@ -0,0 +1,155 @@
+use crate::rope::*;
+
+/// A run of ropes joined end to end through intermediate nodes.
+/// `nodes` includes both end points, so `ropes[i]` joins `nodes[i]` and `nodes[i + 1]`.
+#[derive(Debug, Clone, Default)]
+pub struct Chain {
+    pub nodes: Vec<usize>,
+    pub ropes: Vec<usize>,
+    pub segment_length: f32,
+    /// Tether every node to the chain's fixed ends so it can't stretch, see `Tether`.
+    pub lra: bool,
+    /// How strongly each joint is held straight, see `World::set_chain_bending`.
+    pub bending: f32,
+
+    // Nodes and ropes taken out of the chain by reeling in, kept for reuse when paying out
+    spare_nodes: Vec<usize>,
+    spare_ropes: Vec<usize>,
+}
+
+impl Chain {
+    pub fn length(&self, world: &World) -> f32 {
+        self.ropes.iter().map(|&rid| world.get_rope(rid).length()).sum()
+    }
+}
+
+impl World {
+    /// Returns `None` without adding anything if the segments would have no length,
+    /// from the ends being on top of each other or no slack.
+    pub fn add_chain(&mut self, from: usize, to: usize, segments: usize, slack: f32) -> Option<usize> {
+        debug_assert!(segments > 0);
+        debug_assert!(from != to);
+
+        let from_pos = self.get_node(from).pos;
+        let to_pos = self.get_node(to).pos;
+        let segment_length = from_pos.dist(to_pos) * slack / segments as f32;
+        if (!(segment_length.is_finite() && segment_length > 0.0)) {
+            return None;
+        }
+
+        let mut chain = Chain {
+            segment_length,
+            ..Chain::default()
+        };
+        chain.nodes.push(from);
+
+        for i in 1..segments {
+            // Start out on the straight line between the ends and let the slack sag under load
+            let p = from_pos.add(to_pos.sub(from_pos).mult(i as f32 / segments as f32));
+            chain.nodes.push(self.add_node(p.x, p.y));
+        }
+
+        chain.nodes.push(to);
+
+        for i in 0..segments {
+            let rope_id = self.add_rope_with_length(chain.nodes[i], chain.nodes[i + 1], segment_length);
+            chain.ropes.push(rope_id);
+        }
+
+        self.chains.push(chain);
+        Some(self.chains.len() - 1)
+    }
+
+    pub fn get_chain(&self, id: usize) -> &Chain {
+        &self.chains[id]
+    }
+
+    /// Pay out (positive delta) or reel in (negative delta) rope at the `from` end of a chain.
+    /// Segments are split or merged at the winch so they stay close to the chain's segment length.
+    pub fn winch_chain(&mut self, id: usize, delta: f32) {
+        const MIN_SEGMENT_FRACTION: f32 = 0.05;
+        // Paying out more than this many segments at once leaves the rest on the head rope
+        const MAX_RESEGMENTS: usize = 1024;
+
+        let segment_length = self.chains[id].segment_length;
+        let mut head_length = self.get_rope(self.chains[id].ropes[0]).length() + delta;
+
+        for _ in 0..MAX_RESEGMENTS {
+            if (head_length > segment_length * 1.5) {
+                self.split_chain_head(id);
+                head_length -= segment_length;
+            } else if (head_length < segment_length * 0.5 && self.chains[id].ropes.len() > 1) {
+                head_length += self.merge_chain_head(id);
+            } else {
+                break;
+            }
+        }
+
+        let head_rope = self.chains[id].ropes[0];
+        self.set_rope_length(head_rope, head_length.max(segment_length * MIN_SEGMENT_FRACTION));
+    }
+
+    // Split a full segment off the far side of the head rope
+    fn split_chain_head(&mut self, id: usize) {
+        let segment_length = self.chains[id].segment_length;
+        let a = self.chains[id].nodes[0];
+        let b = self.chains[id].nodes[1];
+        let head_rope = self.chains[id].ropes[0];
+
+        let a_pos = self.get_node(a).pos;
+        let b_node = self.get_node(b);
+        let pos = b_node.pos.project_dist_towards(a_pos, segment_length.min(a_pos.dist(b_node.pos) * 0.5));
+
+        let n = match self.chains[id].spare_nodes.pop() {
+            Some(n) => {
+                self.set_node_pos(n, pos, MoveMode::Reset);
+                n
+            }
+            None => self.add_node(pos.x, pos.y),
+        };
+
+        // Parked nodes change type directly, they aren't interesting to the game
+        self.nodes.set_node_type(n, NodeType::Free);
+        self.set_node_vel(n, b_node.vel);
+
+        self.get_rope_mut(head_rope).to = n;
+        self.mark_topology_dirty();
+
+        let new_rope = match self.chains[id].spare_ropes.pop() {
+            Some(r) => {
+                let rope = self.get_rope_mut(r);
+                rope.from = n;
+                rope.to = b;
+                rope.broken = false;
+                self.set_rope_length(r, segment_length);
+                r
+            }
+            None => self.add_rope_with_length(n, b, segment_length),
+        };
+
+        let chain = &mut self.chains[id];
+        chain.nodes.insert(1, n);
+        chain.ropes.insert(1, new_rope);
+    }
+
+    // Join the head rope to the node after next, parking the node and rope in between.
+    // Returns the length of the parked rope so it can be added onto the head rope.
+    fn merge_chain_head(&mut self, id: usize) -> f32 {
+        let b = self.chains[id].nodes[1];
+        let c = self.chains[id].nodes[2];
+        let head_rope = self.chains[id].ropes[0];
+        let next_rope = self.chains[id].ropes[1];
+
+        self.get_rope_mut(head_rope).to = c;
+        self.break_rope(next_rope);
+        self.nodes.set_node_type(b, NodeType::Fixed);
+
+        let chain = &mut self.chains[id];
+        chain.nodes.remove(1);
+        chain.ropes.remove(1);
+        chain.spare_nodes.push(b);
+        chain.spare_ropes.push(next_rope);
+
+        self.get_rope(next_rope).length()
+    }
+}
@@ -0,0 +1,493 @@
+use crate::events::Event;
+use crate::rope::*;
+
+/// Extra room when looking for contacts, for ropes dragging nodes into a collider while solving.
+/// Nodes moving slower than this per tick are already caught without sweeping.
+pub(crate) const CONTACT_MARGIN: f32 = 1.0;
+
+/// Anything nodes can't pass into, described by its signed distance.
+pub trait ColliderShape: Send + Sync {
+    /// Distance from `p` to the surface, negative inside, and the unit normal pointing out
+    /// of the surface nearest to `p`.
+    fn distance(&self, p: Vec2) -> (f32, Vec2);
+}
+
+pub struct Collider {
+    pub shape: Box<dyn ColliderShape>,
+    pub material: Material,
+    pub filter: CollisionFilter,
+}
+
+/// Which layers something is on, and which layers it collides with.
+/// Two things only collide when each one's mask includes a layer of the other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CollisionFilter {
+    pub layer: u32,
+    pub mask: u32,
+}
+
+impl Default for CollisionFilter {
+    fn default() -> Self {
+        Self {
+            layer: 1,
+            mask: u32::MAX,
+        }
+    }
+}
+
+impl CollisionFilter {
+    pub fn new(layer: u32, mask: u32) -> Self {
+        Self { layer, mask }
+    }
+
+    pub fn collides(&self, other: &CollisionFilter) -> bool {
+        (self.layer & other.mask) != 0 && (other.layer & self.mask) != 0
+    }
+}
+
+/// How a surface grips and bounces. Colliders and nodes both have one,
+/// a contact multiplies their frictions and takes the bouncier restitution.
+/// The default is neutral, so nodes without a material behave as the collider says.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Material {
+    /// Coulomb friction, a sliding node sticks while its speed along the surface is under
+    /// this times the speed it hit the surface with.
+    pub static_friction: f32,
+    /// Once sliding, how much speed along the surface a node loses per unit of speed it hits the surface with.
+    pub kinetic_friction: f32,
+    /// Fraction of a node's speed into the surface it bounces back with.
+    pub restitution: f32,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            static_friction: 1.0,
+            kinetic_friction: 1.0,
+            restitution: 0.0,
+        }
+    }
+}
+
+impl Material {
+    /// Somewhere between concrete and wood.
+    pub fn ground() -> Self {
+        Self {
+            static_friction: 0.6,
+            kinetic_friction: 0.4,
+            restitution: 0.0,
+        }
+    }
+
+    pub fn combine(&self, other: &Material) -> Material {
+        Material {
+            static_friction: self.static_friction * other.static_friction,
+            kinetic_friction: self.kinetic_friction * other.kinetic_friction,
+            restitution: self.restitution.max(other.restitution),
+        }
+    }
+}
+
+/// Node materials, with the neutral material always at index 0.
+pub struct Materials {
+    list: Vec<Material>,
+}
+
+impl Default for Materials {
+    fn default() -> Self {
+        Self {
+            list: vec![Material::default()],
+        }
+    }
+}
+
+impl Materials {
+    pub fn add(&mut self, material: Material) -> usize {
+        debug_assert!(self.list.len() <= u16::MAX as usize);
+        self.list.push(material);
+        self.list.len() - 1
+    }
+
+    pub fn get(&self, id: usize) -> &Material {
+        &self.list[id]
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> &mut Material {
+        &mut self.list[id]
+    }
+}
+
+/// A solid disc, such as the planet the blueprint transforms build around.
+/// Inverted circles keep nodes inside instead, like a round arena.
+pub struct Circle {
+    pub centre: Vec2,
+    pub radius: f32,
+    pub inverted: bool,
+}
+
+impl ColliderShape for Circle {
+    fn distance(&self, p: Vec2) -> (f32, Vec2) {
+        let offset = p.sub(self.centre);
+        let dist = offset.mag();
+        let normal = if (dist > 0.0) {
+            offset.mult(1.0 / dist)
+        } else {
+            Vec2::new(0.0, -1.0)
+        };
+
+        if (self.inverted) {
+            (self.radius - dist, normal.mult(-1.0))
+        } else {
+            (dist - self.radius, normal)
+        }
+    }
+}
+
+/// A node close enough to a collider at the start of the tick that the solver might push it in.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Contact {
+    pub node: usize,
+    pub collider: usize,
+    /// The node's speed along the surface normal before solving, negative when moving in.
+    pub approach: f32,
+}
+
+/// A thick rope close enough to a collider that the solver might push it in.
+/// Ropes are only pushed out, bouncing and friction come from the contacts of their end nodes.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RopeContact {
+    pub rope: usize,
+    pub collider: usize,
+}
+
+impl World {
+    pub fn add_collider(&mut self, shape: Box<dyn ColliderShape>, material: Material) -> usize {
+        self.colliders.push(Collider {
+            shape,
+            material,
+            filter: CollisionFilter::default(),
+        });
+        self.islands.wake_all();
+        self.colliders.len() - 1
+    }
+
+    pub fn get_collider(&self, id: usize) -> &Collider {
+        &self.colliders[id]
+    }
+
+    pub fn get_collider_mut(&mut self, id: usize) -> &mut Collider {
+        &mut self.colliders[id]
+    }
+
+    pub fn set_collider_filter(&mut self, id: usize, filter: CollisionFilter) {
+        self.colliders[id].filter = filter;
+        self.islands.wake_all();
+    }
+
+    pub fn set_node_filter(&mut self, id: usize, filter: CollisionFilter) {
+        self.nodes.filter[id] = filter;
+        self.islands.wake_node(id);
+    }
+
+    pub fn set_rope_filter(&mut self, id: usize, filter: CollisionFilter) {
+        self.ropes[id].filter = filter;
+        self.wake_rope(id);
+    }
+
+    pub fn set_rope_thickness(&mut self, id: usize, thickness: f32) {
+        self.ropes[id].thickness = thickness.max(0.0);
+        self.wake_rope(id);
+    }
+
+    pub fn set_node_material(&mut self, id: usize, material: usize) {
+        debug_assert!(material < self.materials.list.len());
+        self.nodes.material[id] = material as u16;
+        self.islands.wake_node(id);
+    }
+
+    /// Look for nodes within a tick's travel of a collider, after integration and before solving.
+    pub(crate) fn find_contacts(&mut self) {
+        self.contacts.clear();
+        if (self.colliders.is_empty()) {
+            return;
+        }
+
+        for node in 0..self.nodes.len() {
+            if (self.weights[node] == 0.0) {
+                continue;
+            }
+
+            let filter = self.nodes.filter[node];
+            let prev_pos = self.nodes.prev_pos[node];
+            let vel = self.nodes.pos[node].sub(prev_pos);
+            if (vel.mag2() > self.ccd_speed * self.ccd_speed) {
+                self.sweep_node(node);
+            }
+
+            let pos = self.nodes.pos[node];
+            let margin = vel.mag() + CONTACT_MARGIN;
+
+            for (collider, c) in self.colliders.iter().enumerate() {
+                if (!filter.collides(&c.filter)) {
+                    continue;
+                }
+
+                let (dist, normal) = c.shape.distance(pos);
+                if (dist < margin) {
+                    self.contacts.push(Contact {
+                        node,
+                        collider,
+                        approach: vel.dot(normal),
+                    });
+                }
+            }
+        }
+
+        self.rope_contacts.clear();
+        for (rope_id, rope) in self.ropes.iter().enumerate() {
+            if (rope.broken || rope.thickness <= 0.0) {
+                continue;
+            }
+            if (self.weights[rope.from] == 0.0 && self.weights[rope.to] == 0.0) {
+                continue;
+            }
+
+            let from = self.nodes.pos[rope.from];
+            let to = self.nodes.pos[rope.to];
+            let travel = from
+                .sub(self.nodes.prev_pos[rope.from])
+                .mag()
+                .max(to.sub(self.nodes.prev_pos[rope.to]).mag());
+
+            // Distances change no faster than the distance along the rope,
+            // so the midpoint bounds how close any of the rope can be
+            let mid = from.add(to).mult(0.5);
+            let reach = from.dist(to) * 0.5 + rope.thickness * 0.5 + travel + CONTACT_MARGIN;
+
+            for (collider, c) in self.colliders.iter().enumerate() {
+                if (rope.filter.collides(&c.filter) && c.shape.distance(mid).0 < reach) {
+                    self.rope_contacts.push(RopeContact { rope: rope_id, collider });
+                }
+            }
+        }
+    }
+
+    /// Stop a fast node where its path this tick first meets a collider, keeping the rest of its
+    /// motion along the surface. Approach speeds still come from the full velocity so it bounces.
+    fn sweep_node(&mut self, node: usize) {
+        let from = self.nodes.prev_pos[node];
+        let to = self.nodes.pos[node];
+        let filter = self.nodes.filter[node];
+
+        let mut first: Option<(f32, usize)> = None;
+        for (collider, c) in self.colliders.iter().enumerate() {
+            if (!filter.collides(&c.filter)) {
+                continue;
+            }
+
+            if let Some(t) = sweep(c.shape.as_ref(), from, to) {
+                if (first.is_none_or(|(first_t, _)| t < first_t)) {
+                    first = Some((t, collider));
+                }
+            }
+        }
+
+        if let Some((t, collider)) = first {
+            let hit = from.add(to.sub(from).mult(t));
+            let normal = self.colliders[collider].shape.distance(hit).1;
+            let rest = to.sub(hit);
+            self.nodes.pos[node] = hit.add(rest.sub(normal.mult(rest.dot(normal).min(0.0))));
+        }
+    }
+
+    /// Bounce and slow the nodes left touching a collider after solving, and report new contacts.
+    pub(crate) fn respond_to_contacts(&mut self) {
+        // Closer than this counts as touching
+        const SLOP: f32 = 0.01;
+        // Slower impacts than this don't bounce, so resting nodes settle
+        const MIN_BOUNCE_SPEED: f32 = 0.5;
+
+        let was_touching = std::mem::take(&mut self.touching);
+        let mut touching = Vec::with_capacity(was_touching.len());
+
+        for contact in &self.contacts {
+            let collider = &self.colliders[contact.collider];
+            let pos = self.nodes.pos[contact.node];
+            let (dist, normal) = collider.shape.distance(pos);
+            if (dist > SLOP) {
+                continue;
+            }
+
+            let vel = pos.sub(self.nodes.prev_pos[contact.node]);
+            let normal_speed = vel.dot(normal);
+            let tangent_vel = vel.sub(normal.mult(normal_speed));
+
+            let material = collider.material.combine(self.materials.get(self.nodes.material[contact.node] as usize));
+
+            let bounce = if (contact.approach < -MIN_BOUNCE_SPEED) {
+                -contact.approach * material.restitution
+            } else {
+                0.0
+            };
+            let out_speed = normal_speed.max(bounce);
+            let impulse = (out_speed - contact.approach.min(0.0)).max(0.0);
+
+            let tangent_speed = tangent_vel.mag();
+            let mut pos = pos;
+            let tangent_vel = if (tangent_speed <= material.static_friction * impulse) {
+                // Sticking nodes go back to where they touched down, otherwise being pushed
+                // out of the surface each tick would creep them down slopes
+                pos = pos.sub(tangent_vel);
+                self.nodes.pos[contact.node] = pos;
+                Vec2::default()
+            } else {
+                let slowed = (tangent_speed - material.kinetic_friction * impulse).max(0.0);
+                tangent_vel.mult(slowed / tangent_speed)
+            };
+
+            let new_vel = normal.mult(out_speed).add(tangent_vel);
+            self.nodes.prev_pos[contact.node] = pos.sub(new_vel);
+
+            let key = (contact.node, contact.collider);
+            touching.push(key);
+            if (was_touching.binary_search(&key).is_err() && impulse > self.contact_event_impulse) {
+                self.events.push_back(Event::Contact {
+                    node: contact.node,
+                    collider: contact.collider,
+                    impulse,
+                    pos,
+                });
+            }
+        }
+
+        // Sleeping nodes skip contacts but are still resting where they were, so waking them
+        // doesn't count as touching down again
+        let len = touching.len();
+        touching.extend(was_touching.iter().filter(|&&(node, _)| self.nodes.is_asleep(node)));
+        if (touching.len() > len) {
+            touching.sort_unstable();
+        }
+
+        self.touching = touching;
+    }
+}
+
+/// How far along the path from `from` to `to`, as a fraction, a point first touches the shape.
+/// Steps by the distance to the surface each time, so however thin the shape the path can't
+/// jump over it. Paths starting in contact and heading into the surface hit straight away,
+/// those heading out are left to the usual contacts.
+fn sweep(shape: &dyn ColliderShape, from: Vec2, to: Vec2) -> Option<f32> {
+    // Close enough to count as touching
+    const HIT_DIST: f32 = 0.01;
+    // Paths skimming along a surface give up, and end close enough for the usual contacts
+    const MAX_STEPS: usize = 32;
+
+    let length = from.dist(to);
+    if (length == 0.0) {
+        return None;
+    }
+
+    let (start, normal) = shape.distance(from);
+    if (start < HIT_DIST) {
+        return if (to.sub(from).dot(normal) < 0.0) { Some(0.0) } else { None };
+    }
+
+    let mut travelled = 0.0;
+    for _ in 0..MAX_STEPS {
+        let dist = shape.distance(from.add(to.sub(from).mult(travelled / length))).0;
+        if (dist < HIT_DIST) {
+            return Some(travelled / length);
+        }
+
+        travelled += dist;
+        if (travelled >= length) {
+            return None;
+        }
+    }
+
+    None
+}
+
+/// The point on the segment from `a` to `b` nearest to the shape's surface, as the fraction of
+/// the way along and the distance and normal there. Samples along the segment,
+/// then narrows in around the closest sample.
+fn closest_on_segment(shape: &dyn ColliderShape, a: Vec2, b: Vec2, spacing: f32) -> (f32, f32, Vec2) {
+    const MAX_SAMPLES: usize = 32;
+    const REFINE_STEPS: usize = 8;
+
+    let at = |t: f32| a.add(b.sub(a).mult(t));
+
+    let samples = ((a.dist(b) / spacing.max(1.0)).ceil() as usize).clamp(1, MAX_SAMPLES);
+    let mut best_t = 0.0;
+    let mut best_dist = f32::INFINITY;
+    for i in 0..=samples {
+        let t = i as f32 / samples as f32;
+        let dist = shape.distance(at(t)).0;
+        if (dist < best_dist) {
+            best_t = t;
+            best_dist = dist;
+        }
+    }
+
+    let step = 1.0 / samples as f32;
+    let mut lo = (best_t - step).max(0.0);
+    let mut hi = (best_t + step).min(1.0);
+    for _ in 0..REFINE_STEPS {
+        let third = (hi - lo) / 3.0;
+        if (shape.distance(at(lo + third)).0 < shape.distance(at(hi - third)).0) {
+            hi -= third;
+        } else {
+            lo += third;
+        }
+    }
+
+    let t = (lo + hi) * 0.5;
+    let (dist, normal) = shape.distance(at(t));
+    if (dist < best_dist) {
+        (t, dist, normal)
+    } else {
+        (best_t, best_dist, shape.distance(at(best_t)).1)
+    }
+}
+
+/// Push a thick rope back out of its collider at its deepest point, moving each end
+/// by how close it is to that point. Returns how far in the rope was.
+pub(crate) fn solve_rope_contact(contact: &RopeContact, ropes: &[Rope], colliders: &[Collider], pos: &mut [Vec2], weights: &[f32]) -> f32 {
+    let rope = &ropes[contact.rope];
+    if (rope.broken) {
+        return 0.0;
+    }
+
+    let radius = rope.thickness * 0.5;
+    let a = pos[rope.from];
+    let b = pos[rope.to];
+    let (t, dist, normal) = closest_on_segment(colliders[contact.collider].shape.as_ref(), a, b, radius);
+    let depth = radius - dist;
+    if (depth <= 0.0) {
+        return 0.0;
+    }
+
+    let w_a = weights[rope.from] * (1.0 - t);
+    let w_b = weights[rope.to] * t;
+    let denom = w_a * (1.0 - t) + w_b * t;
+    if (denom == 0.0) {
+        return depth;
+    }
+
+    let s = depth / denom;
+    pos[rope.from] = a.add(normal.mult(s * w_a));
+    pos[rope.to] = b.add(normal.mult(s * w_b));
+    depth
+}
+
+/// Push a node back out of its collider, returning how far in it was.
+pub(crate) fn solve_contact(contact: &Contact, colliders: &[Collider], pos: &mut [Vec2]) -> f32 {
+    let p = pos[contact.node];
+    let (dist, normal) = colliders[contact.collider].shape.distance(p);
+    if (dist >= 0.0) {
+        return 0.0;
+    }
+
+    pos[contact.node] = p.add(normal.mult(-dist));
+    -dist
+}
@@ -0,0 +1,368 @@
+use std::fs::File;
+use std::io::Read;
+
+use crate::colliders::ColliderShape;
+use crate::rope::*;
+
+/// Collider from a hand drawn mask, stored as the signed distance to the mask's edge at the
+/// centre of every pixel and sampled bilinearly in between.
+pub struct DistanceField {
+    // Including a one pixel empty border around the mask
+    width: usize,
+    height: usize,
+    // In pixels, negative inside
+    distances: Vec<f32>,
+
+    /// World position of the image's top left corner.
+    pub origin: Vec2,
+    /// World units per pixel.
+    pub scale: f32,
+}
+
+impl DistanceField {
+    /// `solid` holds a row after row flag for each pixel.
+    pub fn from_mask(width: usize, height: usize, solid: &[bool], origin: Vec2, scale: f32) -> Self {
+        debug_assert!(width > 0 && height > 0);
+        debug_assert!(solid.len() == width * height);
+        debug_assert!(scale > 0.0);
+
+        // Everything off the image is empty, so pad it with empty pixels to give solid pixels
+        // along the edge a surface there too
+        let width = width + 2;
+        let height = height + 2;
+        let padded: Vec<bool> = (0..width * height)
+            .map(|i| {
+                let (x, y) = (i % width, i / width);
+                x > 0 && y > 0 && x < width - 1 && y < height - 1 && solid[(y - 1) * (width - 2) + x - 1]
+            })
+            .collect();
+
+        let to_solid = squared_distances(width, height, |i| padded[i]);
+        let to_empty = squared_distances(width, height, |i| !padded[i]);
+
+        // Edges run between pixel centres, half a pixel from the nearest one on the other side
+        let distances = (0..width * height)
+            .map(|i| {
+                if (padded[i]) {
+                    0.5 - to_empty[i].sqrt() as f32
+                } else {
+                    to_solid[i].sqrt() as f32 - 0.5
+                }
+            })
+            .collect();
+
+        Self {
+            width,
+            height,
+            distances,
+            origin,
+            scale,
+        }
+    }
+
+    /// Load a PGM or PNG mask, solid wherever pixels are brighter than `threshold`, from 0 to 1.
+    /// Images with an alpha channel use that instead of brightness, like sprite masks.
+    pub fn load(path: &str, origin: Vec2, scale: f32, threshold: f32) -> Result<Self, String> {
+        let mut bytes = vec![];
+        File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut bytes))
+            .map_err(|e| format!("Could not read {}: {}", path, e))?;
+
+        let (width, height, values) = if (bytes.starts_with(b"\x89PNG")) {
+            read_png(&bytes)?
+        } else if (bytes.starts_with(b"P2") || bytes.starts_with(b"P5")) {
+            read_pgm(&bytes)?
+        } else {
+            return Err(format!("{} is not a PNG or PGM image", path));
+        };
+
+        if (width == 0 || height == 0) {
+            return Err(format!("{} is empty", path));
+        }
+
+        let solid: Vec<bool> = values.iter().map(|&v| v > threshold).collect();
+        Ok(Self::from_mask(width, height, &solid, origin, scale))
+    }
+
+    // Bilinear sample at a position in pixels, clamped to the pixel centres
+    fn sample(&self, x: f32, y: f32) -> f32 {
+        let x = (x - 0.5).clamp(0.0, (self.width - 1) as f32);
+        let y = (y - 0.5).clamp(0.0, (self.height - 1) as f32);
+        let x0 = (x as usize).min(self.width.saturating_sub(2));
+        let y0 = (y as usize).min(self.height.saturating_sub(2));
+        let x1 = (x0 + 1).min(self.width - 1);
+        let y1 = (y0 + 1).min(self.height - 1);
+        let tx = x - x0 as f32;
+        let ty = y - y0 as f32;
+
+        let at = |x: usize, y: usize| self.distances[y * self.width + x];
+        let top = at(x0, y0) * (1.0 - tx) + at(x1, y0) * tx;
+        let bottom = at(x0, y1) * (1.0 - tx) + at(x1, y1) * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+}
+
+impl ColliderShape for DistanceField {
+    fn distance(&self, p: Vec2) -> (f32, Vec2) {
+        // Shifted over the empty border
+        let local = p.sub(self.origin).mult(1.0 / self.scale).add(Vec2::new(1.0, 1.0));
+
+        // Past the pixel centres, come back in to the nearest one. Everything off the image is
+        // empty, so the distance is at least as far as the image
+        let clamped = Vec2::new(
+            local.x.clamp(0.5, self.width as f32 - 0.5),
+            local.y.clamp(0.5, self.height as f32 - 0.5),
+        );
+        let outside = local.dist(clamped);
+        let dist = self.sample(clamped.x, clamped.y);
+        if (outside > 0.0 && outside >= dist - outside) {
+            return (outside * self.scale, local.sub(clamped).mult(1.0 / outside));
+        }
+
+        let gradient = Vec2::new(
+            self.sample(clamped.x + 1.0, clamped.y) - self.sample(clamped.x - 1.0, clamped.y),
+            self.sample(clamped.x, clamped.y + 1.0) - self.sample(clamped.x, clamped.y - 1.0),
+        );
+        let normal = if (gradient.mag2() > 0.0) {
+            gradient.norm()
+        } else {
+            Vec2::new(0.0, -1.0)
+        };
+
+        ((dist - outside) * self.scale, normal)
+    }
+}
+
+// Squared distance in pixels from every pixel to the nearest one matching `target`,
+// with Felzenszwalb and Huttenlocher's transform down every column then along every row
+fn squared_distances<F: Fn(usize) -> bool>(width: usize, height: usize, target: F) -> Vec<f64> {
+    const FAR: f64 = 1e20;
+
+    let mut grid: Vec<f64> = (0..width * height).map(|i| if (target(i)) { 0.0 } else { FAR }).collect();
+
+    let n = width.max(height);
+    let mut f = vec![0.0; n];
+    let mut d = vec![0.0; n];
+    let mut v = vec![0; n];
+    let mut z = vec![0.0; n + 1];
+
+    for x in 0..width {
+        for y in 0..height {
+            f[y] = grid[y * width + x];
+        }
+        transform_1d(&f[..height], &mut d[..height], &mut v, &mut z);
+        for y in 0..height {
+            grid[y * width + x] = d[y];
+        }
+    }
+
+    for row in grid.chunks_mut(width) {
+        f[..width].copy_from_slice(row);
+        transform_1d(&f[..width], row, &mut v, &mut z);
+    }
+
+    grid
+}
+
+// The lower envelope of parabolas rooted at each sample of `f`
+fn transform_1d(f: &[f64], d: &mut [f64], v: &mut [usize], z: &mut [f64]) {
+    let n = f.len();
+    let mut k = 0;
+    v[0] = 0;
+    z[0] = f64::NEG_INFINITY;
+    z[1] = f64::INFINITY;
+
+    for q in 1..n {
+        let parabola = |p: usize| (f[q] + (q * q) as f64 - f[p] - (p * p) as f64) / (2 * (q - p)) as f64;
+
+        // Drop the parabolas this one is lower than all the way along
+        let mut s = parabola(v[k]);
+        while (s <= z[k]) {
+            k -= 1;
+            s = parabola(v[k]);
+        }
+
+        k += 1;
+        v[k] = q;
+        z[k] = s;
+        z[k + 1] = f64::INFINITY;
+    }
+
+    k = 0;
+    for (q, dq) in d.iter_mut().enumerate() {
+        while (z[k + 1] < q as f64) {
+            k += 1;
+        }
+        let offset = q as f64 - v[k] as f64;
+        *dq = offset * offset + f[v[k]];
+    }
+}
+
+fn read_png(bytes: &[u8]) -> Result<(usize, usize, Vec<f32>), String> {
+    let mut decoder = png::Decoder::new(bytes);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut buf).map_err(|e| e.to_string())?;
+
+    let channels = frame.color_type.samples();
+    let has_alpha = matches!(frame.color_type, png::ColorType::GrayscaleAlpha | png::ColorType::Rgba);
+    let values = (0..frame.width as usize * frame.height as usize)
+        .map(|i| {
+            let pixel = &buf[i * channels..(i + 1) * channels];
+            let value = if (has_alpha) {
+                pixel[channels - 1]
+            } else if (channels >= 3) {
+                ((pixel[0] as u32 + pixel[1] as u32 + pixel[2] as u32) / 3) as u8
+            } else {
+                pixel[0]
+            };
+            value as f32 / 255.0
+        })
+        .collect();
+
+    Ok((frame.width as usize, frame.height as usize, values))
+}
+
+// Plain (P2) and raw (P5) graymaps
+fn read_pgm(bytes: &[u8]) -> Result<(usize, usize, Vec<f32>), String> {
+    let mut pos = 0;
+    let mut header = [0; 3];
+    let binary = bytes.starts_with(b"P5");
+    next_token(bytes, &mut pos);
+    for value in header.iter_mut() {
+        *value = next_token(bytes, &mut pos)
+            .and_then(|token| token.parse().ok())
+            .ok_or("Bad PGM header")?;
+    }
+
+    let [width, height, max] = header;
+    if (max == 0 || max > u16::MAX as usize) {
+        return Err("Bad PGM max value".to_string());
+    }
+
+    let count = width.checked_mul(height).ok_or("PGM is too large")?;
+    let raw: Vec<usize> = if (binary) {
+        // A single whitespace byte separates the header from the pixels
+        let data = bytes.get(pos + 1..).unwrap_or(&[]);
+        let size = if (max > 255) { 2 } else { 1 };
+        if (count.checked_mul(size).is_none_or(|needed| data.len() < needed)) {
+            return Err("PGM is missing pixels".to_string());
+        }
+
+        (0..count)
+            .map(|i| {
+                if (size == 2) {
+                    (data[i * 2] as usize) << 8 | data[i * 2 + 1] as usize
+                } else {
+                    data[i] as usize
+                }
+            })
+            .collect()
+    } else {
+        let values: Vec<usize> = std::iter::from_fn(|| next_token(bytes, &mut pos))
+            .take(count)
+            .map(|token| token.parse().unwrap_or(0))
+            .collect();
+        if (values.len() < count) {
+            return Err("PGM is missing pixels".to_string());
+        }
+
+        values
+    };
+
+    Ok((width, height, raw.iter().map(|&v| v as f32 / max as f32).collect()))
+}
+
+// The next whitespace separated word, skipping `#` comments, leaving `pos` just past it
+fn next_token<'a>(bytes: &'a [u8], pos: &mut usize) -> Option<&'a str> {
+    loop {
+        while (*pos < bytes.len() && bytes[*pos].is_ascii_whitespace()) {
+            *pos += 1;
+        }
+        if (*pos < bytes.len() && bytes[*pos] == b'#') {
+            while (*pos < bytes.len() && bytes[*pos] != b'\n') {
+                *pos += 1;
+            }
+        } else {
+            break;
+        }
+    }
+
+    let start = *pos;
+    while (*pos < bytes.len() && !bytes[*pos].is_ascii_whitespace()) {
+        *pos += 1;
+    }
+
+    if (start == *pos) {
+        None
+    } else {
+        std::str::from_utf8(&bytes[start..*pos]).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transform(f: &[f64]) -> Vec<f64> {
+        let n = f.len();
+        let mut d = vec![0.0; n];
+        transform_1d(f, &mut d, &mut vec![0; n], &mut vec![0.0; n + 1]);
+        d
+    }
+
+    #[test]
+    fn parabola_envelope() {
+        const FAR: f64 = 1e20;
+        assert_eq!(transform(&[0.0]), vec![0.0]);
+        assert_eq!(transform(&[FAR, 0.0, FAR, FAR]), vec![1.0, 0.0, 1.0, 4.0]);
+        assert_eq!(transform(&[0.0, FAR, FAR, FAR, 0.0]), vec![0.0, 1.0, 4.0, 1.0, 0.0]);
+        // Raised parabolas only win where they're lower
+        assert_eq!(transform(&[0.0, FAR, FAR, 2.0]), vec![0.0, 1.0, 3.0, 2.0]);
+    }
+
+    #[test]
+    fn plain_pgm_with_comments() {
+        let pgm = b"P2\n# made by hand\n3 2 # size\n4\n0 1 2\n# last row\n3 4 4\n";
+        let (width, height, values) = read_pgm(pgm).unwrap();
+        assert_eq!((width, height), (3, 2));
+        assert_eq!(values, vec![0.0, 0.25, 0.5, 0.75, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn raw_pgm() {
+        let mut pgm = b"P5 2 2 255\n".to_vec();
+        pgm.extend([0, 255, 51, 10]);
+        let (width, height, values) = read_pgm(&pgm).unwrap();
+        assert_eq!((width, height), (2, 2));
+        assert_eq!(values, vec![0.0, 1.0, 0.2, 10.0 / 255.0]);
+    }
+
+    #[test]
+    fn raw_pgm_16_bit() {
+        // Most significant byte first
+        let mut pgm = b"P5\n2 1\n65535\n".to_vec();
+        pgm.extend([0xff, 0xff, 0x01, 0x00]);
+        let (_, _, values) = read_pgm(&pgm).unwrap();
+        assert_eq!(values, vec![1.0, 256.0 / 65535.0]);
+    }
+
+    #[test]
+    fn bad_pgms() {
+        assert!(read_pgm(b"P2 2 2 255 0 0 0").is_err());
+        assert!(read_pgm(b"P5 2 2 255\n\x00\x00\x00").is_err());
+        assert!(read_pgm(b"P2 2 2 0 0 0 0 0").is_err());
+        assert!(read_pgm(b"P2 2 x 255").is_err());
+        assert!(read_pgm(b"P5 100000000000 100000000000 255\n").is_err());
+    }
+
+    #[test]
+    fn solid_border() {
+        // Everything off the image is empty, so even a solid mask has a surface round its edge
+        let field = DistanceField::from_mask(4, 3, &[true; 12], Vec2::default(), 1.0);
+        assert!((field.distance(Vec2::new(2.0, 1.5)).0 + 1.5).abs() < 1e-4);
+        assert!((field.distance(Vec2::new(0.25, 1.5)).0 + 0.25).abs() < 1e-4);
+        assert!(field.distance(Vec2::new(-0.5, 1.5)).0 > 0.0);
+    }
+}
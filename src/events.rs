@@ -0,0 +1,27 @@
+use crate::rope::{NodeType, Vec2};
+
+/// Things that happened during the simulation that the game may want to react to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    /// An island lost its last fixed node and is now free falling.
+    IslandDetached { island: usize, node_count: usize },
+
+    /// A rope stretched past its breaking strain. `pos` is the rope's midpoint.
+    RopeBroken { rope: usize, strain: f32, pos: Vec2 },
+
+    /// A node hit a collider, `impulse` is the magnitude of the velocity change.
+    Contact { node: usize, collider: usize, impulse: f32, pos: Vec2 },
+
+    NodeTypeChanged { node: usize, node_type: NodeType, pos: Vec2 },
+}
+
+impl Event {
+    pub fn pos(&self) -> Option<Vec2> {
+        match self {
+            Event::IslandDetached { .. } => None,
+            Event::RopeBroken { pos, .. } => Some(*pos),
+            Event::Contact { pos, .. } => Some(*pos),
+            Event::NodeTypeChanged { pos, .. } => Some(*pos),
+        }
+    }
+}
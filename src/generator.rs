@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::hash::Hash;
 
 use rand::RngCore;
@@ -10,10 +10,12 @@ use crate::rope::*;
 
 #[derive(Default, Serialize)]
 pub struct GeneratedStructure {
+    pub id: usize,
     floor_pinned_nodes: Vec<usize>,
     nodes: Vec<usize>,
     ropes: Vec<usize>,
     ropes_nodraw: Vec<usize>,
+    clusters: Vec<usize>,
 }
 
 impl GeneratedStructure {
@@ -21,6 +23,7 @@ impl GeneratedStructure {
         blue: &Blueprint,
         world: &mut World,
         transform : &Box<dyn crate::blueprint_to_world_transform::BlueprintToWorldTransform>,
+        cell_stiffness: f32,
     ) -> Self {
 
         let mut generated = Self::default();
@@ -38,8 +41,10 @@ impl GeneratedStructure {
             }
         }
 
+        let mut rope_ids = HashMap::new();
         for (from, to, visible) in &blue.ropes {
             let rope_id = world.add_rope(realised_node_ids[*from], realised_node_ids[*to]);
+            rope_ids.insert((*from.min(to), *from.max(to)), rope_id);
             if (*visible) {
                 generated.ropes.push(rope_id);
             } else {
@@ -47,14 +52,54 @@ impl GeneratedStructure {
             }
         }
 
+        // Hold each solid cell square, for as long as all the ropes around and across it last
+        if (cell_stiffness > 0.0) {
+            for cell in &blue.cells {
+                let nodes: Vec<usize> = cell.iter().map(|&id| realised_node_ids[id]).collect();
+                let mut ropes = vec![];
+                for i in 0..cell.len() {
+                    for j in (i + 1)..cell.len() {
+                        if let Some(&rope_id) = rope_ids.get(&(cell[i].min(cell[j]), cell[i].max(cell[j]))) {
+                            ropes.push(rope_id);
+                        }
+                    }
+                }
+
+                generated.clusters.push(world.add_shape_cluster(&nodes, &ropes, cell_stiffness));
+            }
+        }
+
         generated
     }
+
+    pub fn all_nodes(&self) -> Vec<usize> {
+        let mut nodes = self.floor_pinned_nodes.clone();
+        nodes.extend_from_slice(&self.nodes);
+        nodes
+    }
+
+    pub fn all_ropes(&self) -> Vec<usize> {
+        let mut ropes = self.ropes.clone();
+        ropes.extend_from_slice(&self.ropes_nodraw);
+        ropes
+    }
+
+    pub fn drawn_ropes(&self) -> &[usize] {
+        &self.ropes
+    }
+
+    /// The hidden bracing ropes across solid cells.
+    pub fn nodraw_ropes(&self) -> &[usize] {
+        &self.ropes_nodraw
+    }
 }
 
 #[derive(Default)]
 pub struct Blueprint {
     nodes: Vec<(Vec2, bool)>,
     ropes: Vec<(usize, usize, bool)>,
+    // Corner node ids of every solid cell
+    cells: Vec<[usize; 4]>,
 
     node_grid: DenseGrid<Option<usize>>,
     rope_hashset: HashSet<(usize, usize)>,
@@ -77,6 +122,17 @@ impl Blueprint {
         }
     }
 
+    fn add_cell(&mut self, corners: [I2; 4]) {
+        let mut ids = [0; 4];
+        for (id, corner) in ids.iter_mut().zip(corners.iter()) {
+            match self.node_grid.get(*corner) {
+                Some(node_id) => *id = node_id,
+                None => return,
+            }
+        }
+        self.cells.push(ids);
+    }
+
     fn try_add_rope(&mut self, from: I2, to: I2, visible: bool) {
         if let Some(from_node_id) = self.node_grid.get(from) {
             if let Some(to_node_id) = self.node_grid.get(to) {
@@ -274,6 +330,10 @@ impl IntermediateStructure {
 
                         let draw_all_ropes = cell_state == CellState::Scaffolding;
 
+                        if (cell_state == CellState::EdgeBlock) {
+                            blue.add_cell([bottom_left, bottom_right, top_right, top_left]);
+                        }
+
                         // Internal ropes
                         blue.try_add_rope(top_left, bottom_right, draw_all_ropes);
                         blue.try_add_rope(bottom_left, top_right, draw_all_ropes);
@@ -0,0 +1,93 @@
+use crate::colliders::ColliderShape;
+use crate::rope::*;
+
+/// Ground for flat levels, from heights sampled every `spacing` along x starting at `x0`.
+/// The surface runs in straight lines between samples and carries on flat past either end.
+/// Y points down, so everything below the surface (larger y) is solid.
+pub struct Heightmap {
+    pub x0: f32,
+    pub spacing: f32,
+    pub heights: Vec<f32>,
+}
+
+impl Heightmap {
+    pub fn new(x0: f32, spacing: f32, heights: Vec<f32>) -> Self {
+        debug_assert!(spacing > 0.0);
+        debug_assert!(!heights.is_empty());
+
+        Self { x0, spacing, heights }
+    }
+
+    pub fn height_at(&self, x: f32) -> f32 {
+        let last = self.heights.len() - 1;
+        let f = ((x - self.x0) / self.spacing).clamp(0.0, last as f32);
+        let i = (f.floor() as usize).min(last.saturating_sub(1));
+        if (i == last) {
+            return self.heights[last];
+        }
+
+        let t = f - i as f32;
+        self.heights[i] * (1.0 - t) + self.heights[i + 1] * t
+    }
+
+    fn point(&self, i: usize) -> Vec2 {
+        Vec2::new(self.x0 + i as f32 * self.spacing, self.heights[i])
+    }
+
+    // Upwards normal of the surface directly above or below `x`
+    fn normal_at(&self, x: f32) -> Vec2 {
+        let last = self.heights.len() - 1;
+        let f = (x - self.x0) / self.spacing;
+        if (last == 0 || f < 0.0 || f > last as f32) {
+            return Vec2::new(0.0, -1.0);
+        }
+
+        let i = (f.floor() as usize).min(last - 1);
+        let d = self.point(i + 1).sub(self.point(i)).norm();
+        Vec2::new(d.y, -d.x)
+    }
+}
+
+impl ColliderShape for Heightmap {
+    fn distance(&self, p: Vec2) -> (f32, Vec2) {
+        // Deep below the surface only this many segments either side are checked
+        const MAX_SEGMENTS: usize = 64;
+
+        let last = self.heights.len() - 1;
+        let height = self.height_at(p.x);
+        let inside = p.y > height;
+
+        // Straight up or down to the surface is never closer than the nearest point,
+        // so only segments within that range across can beat it
+        let mut closest = Vec2::new(p.x, height);
+        let mut best = (height - p.y).abs();
+
+        let cell = |x: f32| ((x - self.x0) / self.spacing).max(0.0) as usize;
+        let first = cell(p.x - best).min(last);
+        let end = (cell(p.x + best) + 1).min(last).min(first + MAX_SEGMENTS);
+        for i in first..end {
+            let a = self.point(i);
+            let ab = self.point(i + 1).sub(a);
+            let t = (p.sub(a).dot(ab) / ab.mag2()).clamp(0.0, 1.0);
+            let on_segment = a.add(ab.mult(t));
+            let dist = p.dist(on_segment);
+            if (dist < best) {
+                best = dist;
+                closest = on_segment;
+            }
+        }
+
+        let normal = if (best > 0.0) {
+            let away = p.sub(closest).mult(1.0 / best);
+            if (inside) { away.mult(-1.0) } else { away }
+        } else {
+            self.normal_at(p.x)
+        };
+
+        if (inside) {
+            (-best, normal)
+        } else {
+            (best, normal)
+        }
+    }
+}
@@ -0,0 +1,284 @@
+use crate::events::Event;
+use crate::rope::*;
+
+/// Connected components of nodes over unbroken ropes.
+/// Each island is identified by the smallest node id it contains.
+#[derive(Default)]
+pub struct Islands {
+    node_island: Vec<usize>,
+
+    // Indexed by island id, only meaningful for ids that are islands
+    anchored: Vec<bool>,
+    sizes: Vec<usize>,
+    asleep: Vec<bool>,
+    quiet_ticks: Vec<u32>,
+
+    dirty: bool,
+
+    // Wakes are deferred until the next update so they can be requested while islands are dirty
+    pending_wakes: Vec<usize>,
+    wake_all: bool,
+
+    // Per island scratch for `update_sleep`, kept around to avoid reallocating every tick
+    max_energy: Vec<f32>,
+    fell_asleep: Vec<bool>,
+}
+
+impl Islands {
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    pub fn island_of(&self, node: usize) -> usize {
+        self.node_island[node]
+    }
+
+    pub fn is_anchored(&self, island: usize) -> bool {
+        self.anchored[island]
+    }
+
+    pub fn size(&self, island: usize) -> usize {
+        self.sizes[island]
+    }
+
+    pub fn node_asleep(&self, node: usize) -> bool {
+        self.asleep[self.node_island[node]]
+    }
+
+    pub fn wake_node(&mut self, node: usize) {
+        self.pending_wakes.push(node);
+    }
+
+    pub fn wake_all(&mut self) {
+        self.wake_all = true;
+    }
+
+    // Returns whether the island was asleep
+    fn wake_island(&mut self, island: usize) -> bool {
+        let was_asleep = self.asleep[island];
+        self.asleep[island] = false;
+        self.quiet_ticks[island] = 0;
+        was_asleep
+    }
+}
+
+/// An island falls asleep once none of its nodes has had more than `threshold` kinetic energy
+/// (half the mass times the squared distance moved per tick) for `ticks` ticks in a row.
+/// A threshold of zero or less disables sleeping.
+pub struct SleepParams {
+    pub threshold: f32,
+    pub ticks: u32,
+}
+
+impl Default for SleepParams {
+    fn default() -> Self {
+        Self {
+            threshold: 0.00005,
+            ticks: 60,
+        }
+    }
+}
+
+fn find(parents: &mut [usize], mut x: usize) -> usize {
+    while (parents[x] != x) {
+        parents[x] = parents[parents[x]];
+        x = parents[x];
+    }
+    x
+}
+
+impl World {
+    /// Recompute islands if ropes or node types have changed since the last update,
+    /// queueing an event for every island that no longer holds a fixed node.
+    /// Also applies any pending wakes.
+    pub fn update_islands(&mut self) {
+        let mut changed = false;
+        if (self.islands.dirty || self.islands.node_island.len() != self.nodes.len()) {
+            self.rebuild_islands();
+            changed = true;
+        }
+
+        if (self.islands.wake_all) {
+            for island in 0..self.nodes.len() {
+                changed |= self.islands.wake_island(island);
+            }
+            self.islands.wake_all = false;
+        }
+
+        let mut pending_wakes = std::mem::take(&mut self.islands.pending_wakes);
+        for node in pending_wakes.drain(..) {
+            let island = self.islands.node_island[node];
+            changed |= self.islands.wake_island(island);
+        }
+        self.islands.pending_wakes = pending_wakes;
+
+        // The solver only visits the ropes of awake islands
+        if (changed) {
+            self.solver.mark_dirty();
+        }
+    }
+
+    fn rebuild_islands(&mut self) {
+        let node_count = self.nodes.len();
+        let mut parents: Vec<usize> = (0..node_count).collect();
+
+        let ropes = self.ropes.iter().filter(|r| !r.broken).map(|r| (r.from, r.to));
+        let tethers = self.tethers.iter().map(|t| (t.anchor, t.node));
+        let angles = self.angles.iter().flat_map(|a| [(a.a, a.b), (a.b, a.c)]);
+        let pulleys = self.pulleys.iter().flat_map(|p| [(p.a, p.pulley), (p.pulley, p.b)]);
+        let springs = self.springs.iter().map(|s| (s.from, s.to));
+        // Clusters stop holding their shape once one of their ropes breaks
+        let clusters = self
+            .clusters
+            .iter()
+            .filter(|c| !c.ropes.iter().any(|&rid| self.ropes[rid].broken))
+            .flat_map(|c| c.nodes.windows(2).map(|pair| (pair[0], pair[1])));
+
+        let links = ropes.chain(tethers).chain(angles).chain(pulleys).chain(springs).chain(clusters);
+        for (from, to) in links {
+            let a = find(&mut parents, from);
+            let b = find(&mut parents, to);
+
+            // Keep the smaller id as root so it doubles as the island id
+            if (a < b) {
+                parents[b] = a;
+            } else if (b < a) {
+                parents[a] = b;
+            }
+        }
+
+        let mut node_island = vec![0; node_count];
+        let mut anchored = vec![false; node_count];
+        let mut sizes = vec![0; node_count];
+
+        // Islands only stay asleep if every node in them was already asleep
+        let mut asleep = vec![true; node_count];
+        let mut quiet_ticks = vec![u32::MAX; node_count];
+
+        for (id, slot) in node_island.iter_mut().enumerate() {
+            let island = find(&mut parents, id);
+            *slot = island;
+            sizes[island] += 1;
+            if (self.nodes.node_type(id) == NodeType::Fixed) {
+                anchored[island] = true;
+            }
+
+            if (id < self.islands.node_island.len()) {
+                let old_island = self.islands.node_island[id];
+                asleep[island] &= self.islands.asleep[old_island];
+                quiet_ticks[island] = quiet_ticks[island].min(self.islands.quiet_ticks[old_island]);
+            } else {
+                asleep[island] = false;
+                quiet_ticks[island] = 0;
+            }
+        }
+
+        let old = std::mem::replace(
+            &mut self.islands,
+            Islands {
+                node_island,
+                anchored,
+                sizes,
+                asleep,
+                quiet_ticks,
+                dirty: false,
+                pending_wakes: vec![],
+                wake_all: false,
+                max_energy: vec![],
+                fell_asleep: vec![],
+            },
+        );
+
+        self.islands.pending_wakes = old.pending_wakes;
+        self.islands.wake_all = old.wake_all;
+        self.islands.max_energy = old.max_energy;
+        self.islands.fell_asleep = old.fell_asleep;
+
+        let mut reported = vec![false; node_count];
+        for id in 0..old.node_island.len() {
+            let island = self.islands.node_island[id];
+            if (!self.islands.anchored[island] && !reported[island] && old.anchored[old.node_island[id]]) {
+                reported[island] = true;
+                self.events.push_back(Event::IslandDetached {
+                    island,
+                    node_count: self.islands.sizes[island],
+                });
+            }
+        }
+    }
+
+    /// Count how long each awake island has been at rest and put it to sleep once it has been still long enough.
+    pub(crate) fn update_sleep(&mut self) {
+        if (self.sleep.threshold <= 0.0) {
+            return;
+        }
+
+        let node_count = self.nodes.len();
+        let mut max_energy = std::mem::take(&mut self.islands.max_energy);
+        max_energy.clear();
+        max_energy.resize(node_count, 0.0);
+
+        for id in 0..node_count {
+            if (self.nodes.flags[id] & (Nodes::FIXED | Nodes::ASLEEP) != 0) {
+                continue;
+            }
+
+            let speed2 = self.nodes.vel(id).mag2();
+            if (speed2 > 0.0) {
+                let island = self.islands.node_island[id];
+                let energy = 0.5 * speed2 / self.nodes.inv_mass[id];
+                max_energy[island] = max_energy[island].max(energy);
+            }
+        }
+
+        for id in self.actuated_nodes() {
+            max_energy[self.islands.node_island[id]] = f32::INFINITY;
+        }
+
+        let mut fell_asleep = std::mem::take(&mut self.islands.fell_asleep);
+        fell_asleep.clear();
+        fell_asleep.resize(node_count, false);
+
+        let mut any_fell_asleep = false;
+        for island in 0..node_count {
+            if (self.islands.node_island[island] != island || self.islands.asleep[island]) {
+                continue;
+            }
+
+            if (max_energy[island] >= self.sleep.threshold) {
+                self.islands.quiet_ticks[island] = 0;
+                continue;
+            }
+
+            self.islands.quiet_ticks[island] += 1;
+            if (self.islands.quiet_ticks[island] >= self.sleep.ticks) {
+                self.islands.asleep[island] = true;
+                fell_asleep[island] = true;
+                any_fell_asleep = true;
+            }
+        }
+
+        if (any_fell_asleep) {
+            for id in 0..node_count {
+                if (fell_asleep[self.islands.node_island[id]]) {
+                    self.nodes.set_vel(id, Vec2::default());
+                }
+            }
+            self.solver.mark_dirty();
+        }
+
+        self.islands.max_energy = max_energy;
+        self.islands.fell_asleep = fell_asleep;
+    }
+
+    /// Copy island sleep state onto the per node flags read by the solver.
+    pub(crate) fn sync_sleep_flags(&mut self) {
+        for (id, flags) in self.nodes.flags.iter_mut().enumerate() {
+            if (self.islands.node_asleep(id)) {
+                *flags |= Nodes::ASLEEP;
+            } else {
+                *flags &= !Nodes::ASLEEP;
+            }
+        }
+    }
+}
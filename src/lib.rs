@@ -1,24 +1,47 @@
 #![allow(unused_parens)]
 
+mod actuators;
+mod angles;
+mod chain;
+mod colliders;
 mod dense_grid;
+mod distance_field;
+mod events;
 mod generator;
-mod rope;
+mod heightmap;
+mod islands;
+mod pulleys;
+pub mod rope;
+mod rope_collision;
+mod shapes;
+pub mod solver;
+mod springs;
+mod tethers;
 mod blueprint_to_world_transform;
 
 use gms_binder::*;
-use std::ffi::CString;
+use std::ffi::{CStr, CString};
 use std::os::raw::c_char;
 use std::time::Instant;
 
+use colliders::{Circle, CollisionFilter, Material};
+use distance_field::DistanceField;
+use events::*;
 use generator::*;
+use heightmap::Heightmap;
 use rope::*;
+use solver::{SolverMode, SolverOrdering};
 
 static mut GLOBAL_STATE: Option<GlobalState> = None;
 
 struct GlobalState {
     pub t: usize,
     pub world: World,
+    pub structures: Vec<GeneratedStructure>,
+    pub current_event: Option<Event>,
     pub last_tick: Instant,
+    // Shape matching stiffness for the solid cells of new structures, 0 for none
+    pub cell_stiffness: f32,
 }
 
 impl GlobalState {
@@ -26,11 +49,22 @@ impl GlobalState {
         Self {
             t: 0,
             world: World::default(),
+            structures: vec![],
+            current_event: None,
             last_tick: Instant::now(),
+            cell_stiffness: 0.0,
         }
     }
 }
 
+fn move_mode(mode: f64) -> MoveMode {
+    match mode.round() as i32 {
+        0 => MoveMode::Teleport,
+        1 => MoveMode::Move,
+        _ => MoveMode::Reset,
+    }
+}
+
 gms_bind_start!("rope_lib", "rope_lib.dll", "rope");
 
 #[no_mangle]
@@ -39,51 +73,702 @@ pub extern "C" fn reset() -> f64 {
     unsafe {
         GLOBAL_STATE = Some(GlobalState::new());
     }
-    0.0
+    0.0
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn add_node(x: f64, y: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        let id = state.world.add_node(x as f32, y as f32);
+        id as f64
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_fixed(nid: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.set_node_type(nid.round() as usize, NodeType::Fixed);
+        0.0
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_node_pos(nid: f64, x: f64, y: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.set_node_pos(
+            nid.round() as usize,
+            Vec2::new(x as f32, y as f32),
+            MoveMode::Move,
+        );
+        0.0
+    }
+}
+
+// 0 = teleport (keep velocity), 1 = move (displacement becomes velocity), 2 = reset (zero velocity)
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_node_pos_mode(nid: f64, x: f64, y: f64, mode: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.set_node_pos(
+            nid.round() as usize,
+            Vec2::new(x as f32, y as f32),
+            move_mode(mode),
+        );
+        0.0
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn translate_structure(sid: f64, dx: f64, dy: f64, mode: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        let nodes = state.structures[sid.round() as usize].all_nodes();
+        state
+            .world
+            .translate_nodes(&nodes, Vec2::new(dx as f32, dy as f32), move_mode(mode));
+        0.0
+    }
+}
+
+// Angle in degrees
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn rotate_structure(sid: f64, pivot_x: f64, pivot_y: f64, angle: f64, mode: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        let nodes = state.structures[sid.round() as usize].all_nodes();
+        state.world.rotate_nodes(
+            &nodes,
+            Vec2::new(pivot_x as f32, pivot_y as f32),
+            (angle as f32).to_radians(),
+            move_mode(mode),
+        );
+        0.0
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn add_rope(from: f64, to: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        let id = state
+            .world
+            .add_rope(from.round() as usize, to.round() as usize);
+        id as f64
+    }
+}
+
+// Returns -1 if the ends are in the same place or slack isn't above 0
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn add_chain(from: f64, to: f64, segments: f64, slack: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        let from = from.round() as usize;
+        let to = to.round() as usize;
+        if (from == to || slack.is_nan() || slack <= 0.0) {
+            return -1.0;
+        }
+
+        let id = state.world.add_chain(from, to, (segments.round() as usize).max(1), slack as f32);
+        id.map_or(-1.0, |id| id as f64)
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn get_chain_node_count(cid: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_ref().unwrap();
+        state.world.get_chain(cid.round() as usize).nodes.len() as f64
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn get_chain_node(cid: f64, i: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_ref().unwrap();
+        state.world.get_chain(cid.round() as usize).nodes[i.round() as usize] as f64
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn get_chain_rope_count(cid: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_ref().unwrap();
+        state.world.get_chain(cid.round() as usize).ropes.len() as f64
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn get_chain_rope(cid: f64, i: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_ref().unwrap();
+        state.world.get_chain(cid.round() as usize).ropes[i.round() as usize] as f64
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn get_chain_length(cid: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_ref().unwrap();
+        state.world.get_chain(cid.round() as usize).length(&state.world) as f64
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn winch_chain(cid: f64, delta: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.winch_chain(cid.round() as usize, delta as f32);
+        0.0
+    }
+}
+
+// Stop a chain hung from a fixed end stretching past its rest length
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_chain_lra(cid: f64, enabled: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.chains[cid.round() as usize].lra = enabled > 0.5;
+        0.0
+    }
+}
+
+// A max_length of 0 or less uses the current distance between the nodes
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn add_tether(anchor: f64, node: f64, max_length: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        let anchor = anchor.round() as usize;
+        let node = node.round() as usize;
+        let max_length = if (max_length > 0.0) {
+            max_length as f32
+        } else {
+            state.world.get_node(anchor).pos.dist(state.world.get_node(node).pos)
+        };
+        state.world.add_tether(anchor, node, max_length) as f64
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_tether_length(tid: f64, max_length: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.set_tether_length(tid.round() as usize, max_length as f32);
+        0.0
+    }
+}
+
+// Holds the corner a-b-c at its current angle, stiffness from 0 (limits only) to 1
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn add_angle_constraint(a: f64, b: f64, c: f64, stiffness: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        let id = state.world.add_angle_constraint(
+            a.round() as usize,
+            b.round() as usize,
+            c.round() as usize,
+            stiffness as f32,
+        );
+        id as f64
+    }
+}
+
+// Angle in degrees, signed from a round to c
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_angle_rest(aid: f64, angle: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.set_angle_rest(aid.round() as usize, (angle as f32).to_radians());
+        0.0
+    }
+}
+
+// Angles in degrees, the allowed range runs anticlockwise from min to max
+// min == max removes the limits
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_angle_limits(aid: f64, min: f64, max: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        let limits = if (min == max) {
+            None
+        } else {
+            Some(((min as f32).to_radians(), (max as f32).to_radians()))
+        };
+        state.world.set_angle_limits(aid.round() as usize, limits);
+        0.0
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_chain_bending(cid: f64, stiffness: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.set_chain_bending(cid.round() as usize, stiffness as f32);
+        0.0
+    }
+}
+
+// One rope from a over the pulley node to b, using the current distances as its length
+// With a ratio of 2, b moves half as far as a
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn add_pulley(a: f64, pulley: f64, b: f64, ratio: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        let id = state.world.add_pulley(
+            a.round() as usize,
+            pulley.round() as usize,
+            b.round() as usize,
+            (ratio as f32).max(0.01),
+        );
+        id as f64
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_pulley_length(pid: f64, length: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.set_pulley_length(pid.round() as usize, length as f32);
+        0.0
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn get_pulley_length(pid: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_ref().unwrap();
+        state.world.get_pulley(pid.round() as usize).length as f64
+    }
+}
+
+// 0 slides freely, 1 locks the rope to the wheel
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_pulley_friction(pid: f64, friction: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.set_pulley_friction(pid.round() as usize, friction as f32);
+        0.0
+    }
+}
+
+// A bouncy link at the current distance, keep stiffness * inverse mass below about 1
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn add_spring(from: f64, to: f64, stiffness: f64, damping: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        let id = state.world.add_spring(
+            from.round() as usize,
+            to.round() as usize,
+            stiffness as f32,
+            damping as f32,
+        );
+        id as f64
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_spring_length(sid: f64, length: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.set_spring_length(sid.round() as usize, length as f32);
+        0.0
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn get_spring_length(sid: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_ref().unwrap();
+        let spring = state.world.get_spring(sid.round() as usize);
+        state.world.get_node(spring.from).pos.dist(state.world.get_node(spring.to).pos) as f64
+    }
+}
+
+// Oscillate a rope's length about its current length, period in ticks and phase in degrees
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn add_actuator(rid: f64, amplitude: f64, period: f64, phase: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        let id = state.world.add_actuator(
+            rid.round() as usize,
+            amplitude as f32,
+            (period as f32).max(1.0),
+            (phase as f32).to_radians(),
+        );
+        id as f64
+    }
+}
+
+// An amplitude of 0 holds the rope at its base length
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_actuator(aid: f64, amplitude: f64, period: f64, phase: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.set_actuator(
+            aid.round() as usize,
+            amplitude as f32,
+            (period as f32).max(1.0),
+            (phase as f32).to_radians(),
+        );
+        0.0
+    }
+}
+
+// A solid disc, use the blueprint's world centre and the ground radius for the planet
+// Inverted circles keep nodes inside them
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn add_circle_collider(x: f64, y: f64, radius: f64, inverted: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        let circle = Circle {
+            centre: Vec2::new(x as f32, y as f32),
+            radius: radius as f32,
+            inverted: inverted > 0.5,
+        };
+        state.world.add_collider(Box::new(circle), Material::ground()) as f64
+    }
+}
+
+// Ground from a buffer of buffer_f32 heights, `spacing` apart along x starting from x0
+// Everything below the surface is solid
+// Returns -1 if there are no heights or spacing isn't above 0
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn add_heightmap_collider(buffer: *const c_char, count: f64, x0: f64, spacing: f64) -> f64 {
+    unsafe {
+        if (buffer.is_null() || count.is_nan() || count.round() < 1.0 || !(spacing.is_finite() && spacing > 0.0)) {
+            return -1.0;
+        }
+
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        let samples = buffer as *const f32;
+        let heights = (0..count.round() as usize).map(|i| samples.add(i).read_unaligned()).collect();
+        let heightmap = Heightmap::new(x0 as f32, spacing as f32, heights);
+        state.world.add_collider(Box::new(heightmap), Material::ground()) as f64
+    }
+}
+
+// Collider from a PGM or PNG mask with its top left corner at x, y and `scale` world units per pixel
+// Solid where pixels are brighter than threshold (0 to 1), or more opaque for images with alpha
+// Returns -1 if the image can't be loaded or scale isn't above 0
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn add_image_collider(path: *const c_char, x: f64, y: f64, scale: f64, threshold: f64) -> f64 {
+    unsafe {
+        let scale = scale as f32;
+        if (path.is_null() || !(scale.is_finite() && scale > 0.0)) {
+            return -1.0;
+        }
+
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        let path = CStr::from_ptr(path).to_string_lossy();
+        match DistanceField::load(&path, Vec2::new(x as f32, y as f32), scale, threshold as f32) {
+            Ok(field) => state.world.add_collider(Box::new(field), Material::ground()) as f64,
+            Err(_) => -1.0,
+        }
+    }
+}
+
+// Static friction holds a node still, kinetic friction slows it once it slides
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_collider_friction(cid: f64, static_friction: f64, kinetic_friction: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        let material = &mut state.world.get_collider_mut(cid.round() as usize).material;
+        material.static_friction = (static_friction as f32).max(0.0);
+        material.kinetic_friction = (kinetic_friction as f32).max(0.0);
+        0.0
+    }
+}
+
+// 0 doesn't bounce, 1 bounces back at full speed
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_collider_restitution(cid: f64, restitution: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.get_collider_mut(cid.round() as usize).material.restitution = (restitution as f32).clamp(0.0, 1.0);
+        0.0
+    }
+}
+
+// Node frictions multiply the collider's, and the bouncier restitution of the two wins
+// Nodes start on material 0, which leaves the collider's values alone
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn add_material(static_friction: f64, kinetic_friction: f64, restitution: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        let material = Material {
+            static_friction: (static_friction as f32).max(0.0),
+            kinetic_friction: (kinetic_friction as f32).max(0.0),
+            restitution: (restitution as f32).clamp(0.0, 1.0),
+        };
+        state.world.materials.add(material) as f64
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_node_material(nid: f64, mid: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.set_node_material(nid.round() as usize, mid.round() as usize);
+        0.0
+    }
+}
+
+// Collide the whole rope as a capsule this wide, 0 only collides its end nodes
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_rope_thickness(rid: f64, thickness: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.set_rope_thickness(rid.round() as usize, thickness as f32);
+        0.0
+    }
+}
+
+// Thick ropes push each other apart, apart from ropes in the same group
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_rope_collision(enabled: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.rope_collision = enabled > 0.5;
+        0.0
+    }
+}
+
+// Ropes of generated structures start in group structure id + 1, 0 collides with everything
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_rope_group(rid: f64, group: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.set_rope_group(rid.round() as usize, group.round().max(0.0) as u32);
+        0.0
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_structure_rope_thickness(sid: f64, thickness: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        for rope in state.structures[sid.round() as usize].all_ropes() {
+            state.world.set_rope_thickness(rope, thickness as f32);
+        }
+        0.0
+    }
+}
+
+// Layers and masks are bitmasks, everything starts on layer 1 and collides with all layers
+// Two things collide when each one's mask includes a layer of the other
+// Bits are taken from the two's complement, so a mask of -1 collides with every layer
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_node_layers(nid: f64, layer: f64, mask: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.set_node_filter(nid.round() as usize, CollisionFilter::new(layer as i64 as u32, mask as i64 as u32));
+        0.0
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_rope_layers(rid: f64, layer: f64, mask: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.set_rope_filter(rid.round() as usize, CollisionFilter::new(layer as i64 as u32, mask as i64 as u32));
+        0.0
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_collider_layers(cid: f64, layer: f64, mask: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.set_collider_filter(cid.round() as usize, CollisionFilter::new(layer as i64 as u32, mask as i64 as u32));
+        0.0
+    }
+}
+
+// Sets every node and drawn rope of a structure, the hidden bracing ropes are set separately
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_structure_layers(sid: f64, layer: f64, mask: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        let filter = CollisionFilter::new(layer as i64 as u32, mask as i64 as u32);
+        let structure = &state.structures[sid.round() as usize];
+        for nid in structure.all_nodes() {
+            state.world.set_node_filter(nid, filter);
+        }
+        for &rope in structure.drawn_ropes() {
+            state.world.set_rope_filter(rope, filter);
+        }
+        0.0
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_structure_nodraw_layers(sid: f64, layer: f64, mask: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        let filter = CollisionFilter::new(layer as i64 as u32, mask as i64 as u32);
+        for &rope in state.structures[sid.round() as usize].nodraw_ropes() {
+            state.world.set_rope_filter(rope, filter);
+        }
+        0.0
+    }
+}
+
+// Nodes moving faster than this per tick are swept against colliders so they can't tunnel through
+// Defaults to 1, 0 sweeps every moving node
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_ccd_speed(speed: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.ccd_speed = (speed as f32).max(0.0);
+        0.0
+    }
+}
+
+// Contact events are only sent for hits that change a node's speed by more than this
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_contact_event_threshold(impulse: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.contact_event_impulse = (impulse as f32).max(0.0);
+        0.0
+    }
+}
+
+// 0 = sequential, 1 = coloured (parallel), 2 = jacobi (parallel, softer)
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_solver_mode(mode: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.solver.mode = match mode.round() as i32 {
+            0 => SolverMode::Sequential,
+            1 => SolverMode::Coloured,
+            _ => SolverMode::Jacobi,
+        };
+        0.0
+    }
+}
+
+// Only used by the sequential solver
+// 0 = forward, 1 = alternating, 2 = shuffled each tick from seed, 3 = island local
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_solver_ordering(ordering: f64, seed: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.solver.ordering = match ordering.round() as i32 {
+            0 => SolverOrdering::Forward,
+            1 => SolverOrdering::Alternating,
+            2 => SolverOrdering::Shuffled {
+                seed: seed.round() as u64,
+            },
+            _ => SolverOrdering::IslandLocal,
+        };
+        0.0
+    }
+}
+
+// 0 uses one thread per core
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_solver_threads(threads: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.solver.set_threads(threads.round().max(0.0) as usize);
+        0.0
+    }
 }
 
+// Run between min and max passes per tick, stopping once every rope is within tolerance of its length
+// A tolerance of 0 always runs max passes
 #[no_mangle]
 #[gms_bind]
-pub extern "C" fn add_node(x: f64, y: f64) -> f64 {
+pub extern "C" fn set_solver_iterations(min: f64, max: f64, tolerance: f64) -> f64 {
     unsafe {
         let state = GLOBAL_STATE.as_mut().unwrap();
-        let id = state.world.add_node(x as f32, y as f32);
-        id as f64
+        let max = max.round().max(1.0) as usize;
+        state.world.solver.iterations = max;
+        state.world.solver.min_iterations = (min.round().max(1.0) as usize).min(max);
+        state.world.solver.tolerance = tolerance.max(0.0) as f32;
+        0.0
     }
 }
 
 #[no_mangle]
 #[gms_bind]
-pub extern "C" fn set_fixed(nid: f64) -> f64 {
+pub extern "C" fn get_solver_last_iterations() -> f64 {
     unsafe {
-        let state = GLOBAL_STATE.as_mut().unwrap();
-        let mut node = state.world.get_node_mut(nid.round() as usize);
-        node.node_type = NodeType::Fixed;
-        0.0
+        let state = GLOBAL_STATE.as_ref().unwrap();
+        state.world.solver.last_iterations as f64
     }
 }
 
 #[no_mangle]
 #[gms_bind]
-pub extern "C" fn set_node_pos(nid: f64, x: f64, y: f64) -> f64 {
+pub extern "C" fn get_solver_max_error() -> f64 {
     unsafe {
-        let state = GLOBAL_STATE.as_mut().unwrap();
-        let mut node = state.world.get_node_mut(nid.round() as usize);
-        node.pos.x = x as f32;
-        node.pos.y = y as f32;
-        0.0
+        let state = GLOBAL_STATE.as_ref().unwrap();
+        state.world.solver.last_max_error as f64
     }
 }
 
 #[no_mangle]
 #[gms_bind]
-pub extern "C" fn add_rope(from: f64, to: f64) -> f64 {
+pub extern "C" fn get_solver_rms_error() -> f64 {
     unsafe {
-        let state = GLOBAL_STATE.as_mut().unwrap();
-        let id = state
-            .world
-            .add_rope(from.round() as usize, to.round() as usize);
-        id as f64
+        let state = GLOBAL_STATE.as_ref().unwrap();
+        state.world.solver.last_rms_error as f64
     }
 }
 
@@ -136,16 +821,112 @@ pub extern "C" fn get_node_y(id: f64) -> f64 {
     }
 }
 
+// Adds straight to the node's velocity whatever its mass, like forces do
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn apply_impulse(id: f64, x: f64, y: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state
+            .world
+            .apply_impulse(id.round() as usize, Vec2::new(x as f32, y as f32));
+        0.0
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn wake_node(id: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.islands.wake_node(id.round() as usize);
+        0.0
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn get_node_asleep(id: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.update_islands();
+        if (state.world.islands.node_asleep(id.round() as usize)) {
+            1.0
+        } else {
+            0.0
+        }
+    }
+}
+
+// Threshold is the kinetic energy of the liveliest node, half its mass times the squared distance
+// moved per tick. Zero or less disables sleeping
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_sleep_params(threshold: f64, ticks: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.sleep.threshold = threshold as f32;
+        state.world.sleep.ticks = ticks.round() as u32;
+        state.world.islands.wake_all();
+        0.0
+    }
+}
+
+// Mass changes how ropes and springs pull on the node, forces and impulses ignore it
+// Returns -1 and leaves the node alone unless mass is above 0
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_node_mass(id: f64, mass: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        let mass = mass as f32;
+        if (!(mass.is_finite() && mass > 0.0 && (1.0 / mass).is_finite())) {
+            return -1.0;
+        }
+
+        state.world.set_node_mass(id.round() as usize, mass);
+        0.0
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn get_node_mass(id: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_ref().unwrap();
+        1.0 / state.world.get_node(id.round() as usize).inv_mass as f64
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn get_node_reaction_x(id: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_ref().unwrap();
+        state.world.get_node(id.round() as usize).reaction.x as f64
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn get_node_reaction_y(id: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_ref().unwrap();
+        state.world.get_node(id.round() as usize).reaction.y as f64
+    }
+}
+
 #[no_mangle]
 #[gms_bind]
 pub extern "C" fn toggle_node(id: f64) -> f64 {
     unsafe {
         let state = GLOBAL_STATE.as_mut().unwrap();
-        let mut node = state.world.get_node_mut(id.round() as usize);
-        node.node_type = match node.node_type {
+        let id = id.round() as usize;
+        let node_type = match state.world.get_node(id).node_type {
             NodeType::Free => NodeType::Fixed,
             NodeType::Fixed => NodeType::Free,
         };
+        state.world.set_node_type(id, node_type);
 
         0.0
     }
@@ -176,6 +957,37 @@ pub extern "C" fn get_rope_broken(id: f64) -> f64 {
     }
 }
 
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn break_rope(id: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.break_rope(id.round() as usize);
+        0.0
+    }
+}
+
+// Strain at or below zero makes the rope unbreakable
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_rope_break_strain(id: f64, strain: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        let rope = state.world.get_rope_mut(id.round() as usize);
+        rope.break_strain = if (strain > 0.0) { strain as f32 } else { f32::INFINITY };
+        0.0
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn get_rope_strain(id: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_ref().unwrap();
+        state.world.rope_strain(id.round() as usize) as f64
+    }
+}
+
 #[no_mangle]
 #[gms_bind]
 pub extern "C" fn get_rope_from(id: f64) -> f64 {
@@ -194,6 +1006,210 @@ pub extern "C" fn get_rope_to(id: f64) -> f64 {
     }
 }
 
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn get_rope_length(id: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_ref().unwrap();
+        state.world.get_rope(id.round() as usize).length() as f64
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_rope_length(id: f64, length: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.set_rope_length(id.round() as usize, length as f32);
+        0.0
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn scale_rope_length(id: f64, k: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.scale_rope_length(id.round() as usize, k as f32);
+        0.0
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn get_node_island(id: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.update_islands();
+        state.world.islands.island_of(id.round() as usize) as f64
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn get_island_anchored(island: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.update_islands();
+        if (state.world.islands.is_anchored(island.round() as usize)) {
+            1.0
+        } else {
+            0.0
+        }
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn get_island_size(island: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.world.update_islands();
+        state.world.islands.size(island.round() as usize) as f64
+    }
+}
+
+// Pops the next event and returns its type, 0 if the queue is empty
+// 1 = island detached, 2 = rope broken, 3 = contact, 4 = node type changed
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn poll_event() -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.current_event = state.world.events.pop_front();
+        match state.current_event {
+            None => 0.0,
+            Some(Event::IslandDetached { .. }) => 1.0,
+            Some(Event::RopeBroken { .. }) => 2.0,
+            Some(Event::Contact { .. }) => 3.0,
+            Some(Event::NodeTypeChanged { .. }) => 4.0,
+        }
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn get_event_x() -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_ref().unwrap();
+        match state.current_event.and_then(|e| e.pos()) {
+            Some(pos) => pos.x as f64,
+            None => 0.0,
+        }
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn get_event_y() -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_ref().unwrap();
+        match state.current_event.and_then(|e| e.pos()) {
+            Some(pos) => pos.y as f64,
+            None => 0.0,
+        }
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn get_event_rope() -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_ref().unwrap();
+        match state.current_event {
+            Some(Event::RopeBroken { rope, .. }) => rope as f64,
+            _ => -1.0,
+        }
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn get_event_strain() -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_ref().unwrap();
+        match state.current_event {
+            Some(Event::RopeBroken { strain, .. }) => strain as f64,
+            _ => 0.0,
+        }
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn get_event_node() -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_ref().unwrap();
+        match state.current_event {
+            Some(Event::Contact { node, .. }) => node as f64,
+            Some(Event::NodeTypeChanged { node, .. }) => node as f64,
+            _ => -1.0,
+        }
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn get_event_collider() -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_ref().unwrap();
+        match state.current_event {
+            Some(Event::Contact { collider, .. }) => collider as f64,
+            _ => -1.0,
+        }
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn get_event_impulse() -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_ref().unwrap();
+        match state.current_event {
+            Some(Event::Contact { impulse, .. }) => impulse as f64,
+            _ => 0.0,
+        }
+    }
+}
+
+// Same encoding as get_node_type
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn get_event_node_type() -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_ref().unwrap();
+        match state.current_event {
+            Some(Event::NodeTypeChanged { node_type: NodeType::Free, .. }) => 0.0,
+            Some(Event::NodeTypeChanged { node_type: NodeType::Fixed, .. }) => 1.0,
+            _ => -1.0,
+        }
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn get_event_island() -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_ref().unwrap();
+        match state.current_event {
+            Some(Event::IslandDetached { island, .. }) => island as f64,
+            _ => -1.0,
+        }
+    }
+}
+
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn get_event_node_count() -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_ref().unwrap();
+        match state.current_event {
+            Some(Event::IslandDetached { node_count, .. }) => node_count as f64,
+            _ => -1.0,
+        }
+    }
+}
+
 #[no_mangle]
 #[gms_bind]
 pub extern "C" fn get_sim_t() -> f64 {
@@ -208,7 +1224,7 @@ pub extern "C" fn get_sim_t() -> f64 {
 pub extern "C" fn add_static_force(x: f64, y: f64) -> f64 {
     unsafe {
         let state = GLOBAL_STATE.as_mut().unwrap();
-        state.world.forces.push(Box::new(ConstantForce {
+        state.world.add_force(Box::new(ConstantForce {
             force: Vec2::new(x as f32, y as f32),
         }));
 
@@ -221,7 +1237,7 @@ pub extern "C" fn add_static_force(x: f64, y: f64) -> f64 {
 pub extern "C" fn add_inverse_square_force(strength: f64, x: f64, y: f64) -> f64 {
     unsafe {
         let state = GLOBAL_STATE.as_mut().unwrap();
-        state.world.forces.push(Box::new(InverseSquareForce {
+        state.world.add_force(Box::new(InverseSquareForce {
             strength: strength as f32,
             pos: Vec2::new(x as f32, y as f32),
         }));
@@ -230,6 +1246,17 @@ pub extern "C" fn add_inverse_square_force(strength: f64, x: f64, y: f64) -> f64
     }
 }
 
+// Keep the solid cells of structures made after this square, stiffness from 0 (off) to 1 (rigid)
+#[no_mangle]
+#[gms_bind]
+pub extern "C" fn set_cell_shape_matching(stiffness: f64) -> f64 {
+    unsafe {
+        let state = GLOBAL_STATE.as_mut().unwrap();
+        state.cell_stiffness = (stiffness as f32).clamp(0.0, 1.0);
+        0.0
+    }
+}
+
 #[no_mangle]
 #[gms_bind]
 pub extern "C" fn blueprint(x: f64, y: f64, world_x: f64, world_y: f64) -> *const c_char {
@@ -241,21 +1268,30 @@ pub extern "C" fn blueprint(x: f64, y: f64, world_x: f64, world_y: f64) -> *cons
         let transform = blueprint_to_world_transform::HybridTransform::new(Vec2::new(x as f32, y as f32), Vec2::new(world_x as f32, world_y as f32), 20.);
         let boxed_transform = Box::new(transform) as Box<dyn blueprint_to_world_transform::BlueprintToWorldTransform>;
 
-        let generated = GeneratedStructure::from_blueprint(
+        let mut generated = GeneratedStructure::from_blueprint(
             &blueprint,
             &mut state.world,
             &boxed_transform,
+            state.cell_stiffness,
         );
+        generated.id = state.structures.len();
+
+        // A structure's ropes don't collide with each other
+        for rope in generated.all_ropes() {
+            state.world.set_rope_group(rope, generated.id as u32 + 1);
+        }
 
         let json = serde_json::to_string(&generated).unwrap();
         println!("{}", json);
         let c_str_json = CString::new(json).unwrap();
         let p = c_str_json.as_ptr();
         std::mem::forget(c_str_json);
+        state.structures.push(generated);
         p as *const c_char
     }
 }
 
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
 #[gms_bind]
 pub extern "C" fn free_string(s: *mut c_char) -> f64 {
@@ -0,0 +1,157 @@
+use crate::rope::*;
+
+/// One rope running from `a` over the wheel at `pulley` to `b`, so that
+/// `|a - pulley| + ratio * |b - pulley|` never exceeds `length`.
+/// A ratio of 2 makes `b` a block and tackle, moving half as far as `a` for twice the pull.
+#[derive(Debug, Clone, Copy)]
+pub struct Pulley {
+    pub a: usize,
+    pub pulley: usize,
+    pub b: usize,
+    pub length: f32,
+    pub ratio: f32,
+    /// How much the wheel resists the rope sliding over it, from 0 (free) to 1 (locked).
+    pub friction: f32,
+
+    // How much of the rope was on the `a` side at the end of the last tick
+    length_a: f32,
+}
+
+impl World {
+    /// Uses the current distances for the length of rope.
+    pub fn add_pulley(&mut self, a: usize, pulley: usize, b: usize, ratio: f32) -> usize {
+        debug_assert!(a != pulley && b != pulley && a != b);
+        debug_assert!(ratio > 0.0);
+
+        let length_a = self.nodes.pos[a].dist(self.nodes.pos[pulley]);
+        let length_b = self.nodes.pos[b].dist(self.nodes.pos[pulley]);
+        self.pulleys.push(Pulley {
+            a,
+            pulley,
+            b,
+            length: length_a + ratio * length_b,
+            ratio,
+            friction: 0.0,
+            length_a,
+        });
+        self.mark_topology_dirty();
+        self.wake_pulley(self.pulleys.len() - 1);
+        self.pulleys.len() - 1
+    }
+
+    pub fn get_pulley(&self, id: usize) -> &Pulley {
+        &self.pulleys[id]
+    }
+
+    pub fn set_pulley_length(&mut self, id: usize, length: f32) {
+        let pulley = &mut self.pulleys[id];
+        pulley.length = length.max(0.0);
+        pulley.length_a = pulley.length_a.min(pulley.length);
+        self.wake_pulley(id);
+    }
+
+    pub fn set_pulley_friction(&mut self, id: usize, friction: f32) {
+        self.pulleys[id].friction = friction.clamp(0.0, 1.0);
+        self.wake_pulley(id);
+    }
+
+    fn wake_pulley(&mut self, id: usize) {
+        let pulley = self.pulleys[id];
+        self.islands.wake_node(pulley.a);
+        self.islands.wake_node(pulley.pulley);
+        self.islands.wake_node(pulley.b);
+    }
+}
+
+impl Pulley {
+    /// Takes in any slack over the wheel, and if there's friction pulls each side back towards
+    /// the share of rope it had last tick. Returns how much too long the rope was.
+    pub(crate) fn solve(&self, pos: &mut [Vec2], reaction: &mut [Vec2], weights: &[f32]) -> f32 {
+        let error = self.solve_taut(pos, reaction, weights);
+
+        if (self.friction > 0.0) {
+            let length_b = (self.length - self.length_a) / self.ratio;
+            self.solve_side(self.a, self.length_a, pos, reaction, weights);
+            self.solve_side(self.b, length_b, pos, reaction, weights);
+        }
+
+        error
+    }
+
+    fn solve_taut(&self, pos: &mut [Vec2], reaction: &mut [Vec2], weights: &[f32]) -> f32 {
+        let w_a = weights[self.a];
+        let w_p = weights[self.pulley];
+        let w_b = weights[self.b];
+
+        let to_a = pos[self.a].sub(pos[self.pulley]);
+        let to_b = pos[self.b].sub(pos[self.pulley]);
+        let dist_a = to_a.mag();
+        let dist_b = to_b.mag();
+        if (dist_a == 0.0 || dist_b == 0.0) {
+            return 0.0;
+        }
+
+        let error = dist_a + self.ratio * dist_b - self.length;
+        if (error <= 0.0) {
+            return 0.0;
+        }
+
+        let grad_a = to_a.mult(1.0 / dist_a);
+        let grad_b = to_b.mult(self.ratio / dist_b);
+        let grad_p = grad_a.add(grad_b).mult(-1.0);
+
+        let denom = w_a + w_b * self.ratio * self.ratio + w_p * grad_p.mag2();
+        if (denom == 0.0) {
+            return error;
+        }
+
+        let s = -error / denom;
+        let move_a = grad_a.mult(s * w_a);
+        let move_b = grad_b.mult(s * w_b);
+        pos[self.a] = pos[self.a].add(move_a);
+        pos[self.b] = pos[self.b].add(move_b);
+        pos[self.pulley] = pos[self.pulley].add(grad_p.mult(s * w_p));
+
+        // A pinned wheel carries the pull of both sides
+        if (w_p == 0.0) {
+            reaction[self.pulley] = reaction[self.pulley].sub(move_a.add(move_b));
+        }
+
+        error
+    }
+
+    // Friction as a soft limit on one side, at the pulley's friction as stiffness
+    fn solve_side(&self, end: usize, max_length: f32, pos: &mut [Vec2], reaction: &mut [Vec2], weights: &[f32]) {
+        let w_end = weights[end];
+        let w_p = weights[self.pulley];
+        if (w_end == 0.0 && w_p == 0.0) {
+            return;
+        }
+
+        let delta = pos[end].sub(pos[self.pulley]);
+        let dist = delta.mag();
+        if (dist <= max_length || dist == 0.0) {
+            return;
+        }
+
+        let k = self.friction * (dist - max_length) / (dist * (w_end + w_p));
+        let move_end = delta.mult(-k * w_end);
+        pos[end] = pos[end].add(move_end);
+        pos[self.pulley] = pos[self.pulley].add(delta.mult(k * w_p));
+
+        if (w_p == 0.0) {
+            reaction[self.pulley] = reaction[self.pulley].sub(move_end);
+        }
+    }
+
+    // Let the rope settle into however it slid over the wheel this tick,
+    // sharing out any slack in proportion to each side
+    pub(crate) fn update_slide(&mut self, pos: &[Vec2]) {
+        let dist_a = pos[self.a].dist(pos[self.pulley]);
+        let dist_b = pos[self.b].dist(pos[self.pulley]);
+        let used = dist_a + self.ratio * dist_b;
+        if (used > 0.0) {
+            self.length_a = (dist_a * self.length / used).min(self.length);
+        }
+    }
+}
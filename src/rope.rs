@@ -1,15 +1,99 @@
-#[derive(Default)]
+use std::collections::VecDeque;
+
+use crate::actuators::Actuator;
+use crate::angles::AngleConstraint;
+use crate::chain::Chain;
+use crate::colliders::{Collider, CollisionFilter, Contact, Materials, RopeContact, CONTACT_MARGIN};
+use crate::events::Event;
+use crate::islands::{Islands, SleepParams};
+use crate::rope_collision::RopePair;
+use crate::pulleys::Pulley;
+use crate::shapes::ShapeCluster;
+use crate::solver::Solver;
+use crate::springs::{accumulate_springs, Spring};
+use crate::tethers::Tether;
+
 pub struct World {
-    pub nodes: Vec<RopeNode>,
+    pub nodes: Nodes,
     pub ropes: Vec<Rope>,
     pub colliders: Vec<Collider>,
+    pub materials: Materials,
     pub forces: Vec<Box<dyn Force>>,
+    pub chains: Vec<Chain>,
+    pub tethers: Vec<Tether>,
+    pub clusters: Vec<ShapeCluster>,
+    pub angles: Vec<AngleConstraint>,
+    pub pulleys: Vec<Pulley>,
+    pub springs: Vec<Spring>,
+    pub actuators: Vec<Actuator>,
+    pub islands: Islands,
+    pub sleep: SleepParams,
+    pub events: VecDeque<Event>,
+    pub solver: Solver,
+    /// Collide thick ropes with each other as well as with colliders.
+    pub rope_collision: bool,
+    /// Nodes moving further than this in a tick are swept along their path against colliders,
+    /// so they can't skip through thin ones. Zero sweeps every moving node, which gets expensive.
+    pub ccd_speed: f32,
+    /// Only report contacts that change a node's velocity by more than this.
+    pub contact_event_impulse: f32,
+    /// Ticks run so far.
+    pub ticks: u64,
+
+    // Per node scratch buffers, kept around to avoid reallocating every tick
+    force_acc: Vec<Vec2>,
+    spring_acc: Vec<Vec2>,
+    pub(crate) weights: Vec<f32>,
+    pub(crate) contacts: Vec<Contact>,
+    pub(crate) rope_contacts: Vec<RopeContact>,
+    pub(crate) rope_pairs: Vec<RopePair>,
+    pub(crate) rope_bounds: Vec<(usize, Vec2, Vec2)>,
+    pub(crate) rope_cells: Vec<(u64, usize)>,
+    // Node and collider pairs touching at the end of the last tick, sorted
+    pub(crate) touching: Vec<(usize, usize)>,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self {
+            nodes: Nodes::default(),
+            ropes: vec![],
+            colliders: vec![],
+            materials: Materials::default(),
+            forces: vec![],
+            chains: vec![],
+            tethers: vec![],
+            clusters: vec![],
+            angles: vec![],
+            pulleys: vec![],
+            springs: vec![],
+            actuators: vec![],
+            islands: Islands::default(),
+            sleep: SleepParams::default(),
+            events: VecDeque::new(),
+            solver: Solver::default(),
+            rope_collision: false,
+            // Slower nodes are already found by the contact margin
+            ccd_speed: CONTACT_MARGIN,
+            contact_event_impulse: 0.0,
+            ticks: 0,
+
+            force_acc: vec![],
+            spring_acc: vec![],
+            weights: vec![],
+            contacts: vec![],
+            rope_contacts: vec![],
+            rope_pairs: vec![],
+            rope_bounds: vec![],
+            rope_cells: vec![],
+            touching: vec![],
+        }
+    }
 }
 
 impl World {
     pub fn add_node(&mut self, x: f32, y: f32) -> usize {
-        self.nodes.push(RopeNode::new(x, y));
-        self.nodes.len() - 1
+        self.nodes.push(Vec2::new(x, y))
     }
 
     pub fn add_rope(&mut self, from: usize, to: usize) -> usize {
@@ -18,15 +102,63 @@ impl World {
         debug_assert!(from != to);
 
         self.ropes.push(Rope::new(from, to, &self));
+        self.mark_topology_dirty();
+        self.wake_rope(self.ropes.len() - 1);
         self.ropes.len() - 1
     }
 
-    pub fn get_node(&self, id: usize) -> &RopeNode {
-        &self.nodes[id]
+    pub fn add_rope_with_length(&mut self, from: usize, to: usize, length: f32) -> usize {
+        debug_assert!(from < self.nodes.len());
+        debug_assert!(to < self.nodes.len());
+        debug_assert!(from != to);
+
+        self.ropes.push(Rope::with_length(from, to, length));
+        self.mark_topology_dirty();
+        self.wake_rope(self.ropes.len() - 1);
+        self.ropes.len() - 1
     }
 
-    pub fn get_node_mut(&mut self, id: usize) -> &mut RopeNode {
-        &mut self.nodes[id]
+    pub fn add_force(&mut self, force: Box<dyn Force>) {
+        self.forces.push(force);
+        self.islands.wake_all();
+    }
+
+    /// Changes the node's velocity directly whatever its mass, like the world's forces.
+    pub fn apply_impulse(&mut self, id: usize, impulse: Vec2) {
+        let vel = self.nodes.vel(id).add(impulse);
+        self.nodes.set_vel(id, vel);
+        self.islands.wake_node(id);
+    }
+
+    /// Call whenever ropes are added, broken or rewired.
+    pub(crate) fn mark_topology_dirty(&mut self) {
+        self.islands.mark_dirty();
+        self.solver.mark_dirty();
+    }
+
+    pub(crate) fn wake_rope(&mut self, id: usize) {
+        self.islands.wake_node(self.ropes[id].from);
+        self.islands.wake_node(self.ropes[id].to);
+    }
+
+    pub fn get_node(&self, id: usize) -> RopeNode {
+        self.nodes.get(id)
+    }
+
+    pub fn set_node_vel(&mut self, id: usize, vel: Vec2) {
+        self.nodes.set_vel(id, vel);
+        self.islands.wake_node(id);
+    }
+
+    /// Masses too small or large to invert are ignored.
+    pub fn set_node_mass(&mut self, id: usize, mass: f32) {
+        let inv_mass = 1.0 / mass;
+        if (!(mass.is_finite() && mass > 0.0 && inv_mass.is_finite())) {
+            return;
+        }
+
+        self.nodes.inv_mass[id] = inv_mass;
+        self.islands.wake_node(id);
     }
 
     pub fn get_rope(&self, id: usize) -> &Rope {
@@ -37,59 +169,163 @@ impl World {
         &mut self.ropes[id]
     }
 
-    // Done here due to borrow pain
-    fn tick_rope(&mut self, rope_id: usize) {
-        let rope = self.ropes[rope_id];
-
-        if (rope.broken) {
+    pub fn set_node_type(&mut self, id: usize, node_type: NodeType) {
+        if (self.nodes.node_type(id) == node_type) {
             return;
         }
 
-        let from_0 = self.nodes[rope.from].clone();
-        let to_0 = self.nodes[rope.to].clone();
-        //let centre = from_0.pos.add(to_0.pos).mult(0.5);
-        let centre = from_0.pos.add(to_0.pos.sub(from_0.pos).mult(0.5));
+        self.nodes.set_node_type(id, node_type);
+        self.events.push_back(Event::NodeTypeChanged {
+            node: id,
+            node_type,
+            pos: self.nodes.pos[id],
+        });
+        // Anchors change which islands are anchored and where island local ordering starts
+        self.mark_topology_dirty();
+        self.islands.wake_node(id);
+    }
 
-        // TODO trying to get ropes to break?
-        ////let dist = from_0.pos.sub(to_0.pos).mag();
-        ////if (dist > rope.length * 1.5) {
-        ////  // Break!
-        ////  self.ropes[rope_id].broken = true;
-        ////  return;
-        ////}
+    pub fn break_rope(&mut self, id: usize) {
+        self.ropes[id].broken = true;
+        self.mark_topology_dirty();
+        self.wake_rope(id);
+    }
 
-        let half_len = rope.length / 2.0;
+    pub fn rope_strain(&self, id: usize) -> f32 {
+        let rope = &self.ropes[id];
+        let dist = self.nodes.pos[rope.from].dist(self.nodes.pos[rope.to]);
+        (dist - rope.length) / rope.length
+    }
 
-        match (from_0.node_type, to_0.node_type) {
-            (NodeType::Fixed, NodeType::Fixed) => {
-                // Nothing to do, both ends fixed
-                return;
-            }
-            (NodeType::Fixed, NodeType::Free) => {
-                self.nodes[rope.to].pos = centre.project_dist_towards(to_0.pos, half_len);
-            }
-            (NodeType::Free, NodeType::Fixed) => {
-                self.nodes[rope.from].pos = centre.project_dist_towards(from_0.pos, half_len);
+    fn break_overstrained_ropes(&mut self) {
+        for rid in 0..self.ropes.len() {
+            let rope = self.ropes[rid];
+            if (rope.broken || rope.break_strain.is_infinite() || self.nodes.is_asleep(rope.from)) {
+                continue;
             }
-            _ => {
-                self.nodes[rope.from].pos = centre.project_dist_towards(from_0.pos, half_len);
-                self.nodes[rope.to].pos = centre.project_dist_towards(to_0.pos, half_len);
+
+            let strain = self.rope_strain(rid);
+            if (strain > rope.break_strain) {
+                let from = self.nodes.pos[rope.from];
+                let to = self.nodes.pos[rope.to];
+                self.break_rope(rid);
+                self.events.push_back(Event::RopeBroken {
+                    rope: rid,
+                    strain,
+                    pos: from.add(to.sub(from).mult(0.5)),
+                });
             }
         }
     }
 
-    pub fn tick(&mut self, dt_norm: f32) {
-        for node in &mut self.nodes {
-            node.tick(&self.forces, dt_norm);
+    pub fn set_rope_length(&mut self, id: usize, length: f32) {
+        self.ropes[id].length = length.max(Rope::MIN_LENGTH);
+        self.wake_rope(id);
+    }
+
+    pub fn scale_rope_length(&mut self, id: usize, k: f32) {
+        let length = self.ropes[id].length * k;
+        self.set_rope_length(id, length);
+    }
+
+    pub fn set_node_pos(&mut self, id: usize, pos: Vec2, mode: MoveMode) {
+        let delta = pos.sub(self.nodes.pos[id]);
+        self.translate_nodes(&[id], delta, mode);
+    }
+
+    pub fn translate_nodes(&mut self, ids: &[usize], delta: Vec2, mode: MoveMode) {
+        self.transform_nodes(ids, |p| p.add(delta), mode);
+    }
+
+    pub fn rotate_nodes(&mut self, ids: &[usize], pivot: Vec2, angle: f32, mode: MoveMode) {
+        self.transform_nodes(ids, |p| p.rotate_about(pivot, angle), mode);
+    }
+
+    fn transform_nodes<F: Fn(Vec2) -> Vec2>(&mut self, ids: &[usize], f: F, mode: MoveMode) {
+        for &id in ids {
+            let pos = f(self.nodes.pos[id]);
+            self.nodes.prev_pos[id] = match mode {
+                // Transform the previous position too so velocity is carried along
+                MoveMode::Teleport => f(self.nodes.prev_pos[id]),
+                MoveMode::Move => self.nodes.prev_pos[id],
+                MoveMode::Reset => pos,
+            };
+            self.nodes.pos[id] = pos;
+            self.islands.wake_node(id);
+        }
+    }
+
+    fn integrate(&mut self) {
+        let node_count = self.nodes.len();
+        self.force_acc.clear();
+        self.force_acc.resize(node_count, Vec2::default());
+        self.spring_acc.clear();
+        self.spring_acc.resize(node_count, Vec2::default());
+
+        // World forces accelerate every node alike, like gravity, springs push heavy nodes less
+        for force in &self.forces {
+            force.accumulate(&self.nodes.pos, &mut self.force_acc);
         }
+        accumulate_springs(&self.springs, &self.nodes.pos, &self.nodes.prev_pos, &mut self.spring_acc);
 
-        const SIM_ITERS: usize = 8;
-        for _ in 0..SIM_ITERS {
-            for rid in 0..self.ropes.len() {
-                self.tick_rope(rid);
-            }
+        const FRIC: f32 = 0.98;
+
+        let nodes = &mut self.nodes;
+        self.weights.clear();
+        self.weights.resize(node_count, 0.0);
+
+        // Work out who can move first, so integrating is the same arithmetic for every node
+        let flag_iter = self
+            .weights
+            .iter_mut()
+            .zip(nodes.reaction.iter_mut())
+            .zip(nodes.inv_mass.iter())
+            .zip(nodes.flags.iter());
+        for (((weight, reaction), inv_mass), flags) in flag_iter {
+            let still = flags & (Nodes::FIXED | Nodes::ASLEEP) != 0;
+            *weight = if (still) { 0.0 } else { *inv_mass };
+
+            // Sleeping anchors keep the load they had when they fell asleep
+            let asleep = flags & Nodes::ASLEEP != 0;
+            *reaction = if (asleep) { *reaction } else { Vec2::default() };
+        }
+
+        // Nodes that can't move take a zero step
+        let iter = nodes
+            .pos
+            .iter_mut()
+            .zip(nodes.prev_pos.iter_mut())
+            .zip(self.weights.iter())
+            .zip(self.force_acc.iter())
+            .zip(self.spring_acc.iter());
+        for ((((pos, prev_pos), weight), acc), spring) in iter {
+            let moving = *weight != 0.0;
+            let step = if (moving) { 1.0 } else { 0.0 };
+
+            let vel = pos.sub(*prev_pos).mult(FRIC).add(*acc).add(spring.mult(*weight));
+            let next = pos.add(vel.mult(step));
+            *prev_pos = if (moving) { *pos } else { *prev_pos };
+            *pos = next;
         }
     }
+
+    pub fn tick(&mut self, _dt_norm: f32) {
+        self.update_islands();
+        self.sync_sleep_flags();
+
+        self.update_actuators();
+        self.integrate();
+        self.find_contacts();
+        self.find_rope_pairs();
+
+        self.solve_ropes();
+        self.respond_to_contacts();
+
+        self.break_overstrained_ropes();
+        self.update_sleep();
+
+        self.ticks += 1;
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -98,6 +334,17 @@ pub enum NodeType {
     Free,
 }
 
+/// How repositioning a node affects its velocity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoveMode {
+    /// Keep the node's velocity.
+    Teleport,
+    /// The displacement becomes the node's velocity.
+    Move,
+    /// Zero the node's velocity.
+    Reset,
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Vec2 {
     pub x: f32,
@@ -149,6 +396,15 @@ impl Vec2 {
         self.mult(1.0 / self.mag())
     }
 
+    pub fn rotate(&self, angle: f32) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Self::new(self.x * cos - self.y * sin, self.x * sin + self.y * cos)
+    }
+
+    pub fn rotate_about(&self, pivot: Self, angle: f32) -> Self {
+        pivot.add(self.sub(pivot).rotate(angle))
+    }
+
     pub fn project_dist_towards(&self, other: Self, dist: f32) -> Self {
         let diff = other.sub(self.clone());
 
@@ -159,67 +415,154 @@ impl Vec2 {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct RopeNode {
-    pub node_type: NodeType,
-    pub pos: Vec2,
-    prev_pos: Vec2,
+/// Node storage, one array per property so the integration loop can stream through them.
+#[derive(Default)]
+pub struct Nodes {
+    pub pos: Vec<Vec2>,
+    pub prev_pos: Vec<Vec2>,
+    pub inv_mass: Vec<f32>,
+    pub flags: Vec<u8>,
+    /// Index into `World::materials`.
+    pub material: Vec<u16>,
+    pub filter: Vec<CollisionFilter>,
+
+    /// For fixed nodes, the total correction the attached ropes pulled on the node with over the last tick.
+    pub reaction: Vec<Vec2>,
 }
 
-impl RopeNode {
-    fn new(x: f32, y: f32) -> Self {
-        Self {
-            node_type: NodeType::Free,
-            pos: Vec2::new(x, y),
-            prev_pos: Vec2::new(x, y),
+impl Nodes {
+    pub const FIXED: u8 = 1;
+    pub const ASLEEP: u8 = 2;
+
+    pub fn push(&mut self, pos: Vec2) -> usize {
+        self.pos.push(pos);
+        self.prev_pos.push(pos);
+        self.inv_mass.push(1.0);
+        self.flags.push(0);
+        self.material.push(0);
+        self.filter.push(CollisionFilter::default());
+        self.reaction.push(Vec2::default());
+        self.pos.len() - 1
+    }
+
+    pub fn len(&self) -> usize {
+        self.pos.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos.is_empty()
+    }
+
+    pub fn get(&self, id: usize) -> RopeNode {
+        RopeNode {
+            node_type: self.node_type(id),
+            pos: self.pos[id],
+            vel: self.vel(id),
+            inv_mass: self.inv_mass[id],
+            reaction: self.reaction[id],
         }
     }
 
-    fn tick(&mut self, forces: &[Box<dyn Force>], dt_norm: f32) {
-        if (self.node_type == NodeType::Fixed) {
-            return;
+    pub fn node_type(&self, id: usize) -> NodeType {
+        if (self.flags[id] & Self::FIXED != 0) {
+            NodeType::Fixed
+        } else {
+            NodeType::Free
         }
+    }
 
-        //let mut vel = self.pos.sub(self.prev_pos).mult(dt_norm);
-        let mut vel = self.pos.sub(self.prev_pos);
+    pub fn set_node_type(&mut self, id: usize, node_type: NodeType) {
+        match node_type {
+            NodeType::Fixed => self.flags[id] |= Self::FIXED,
+            NodeType::Free => self.flags[id] &= !Self::FIXED,
+        }
+    }
 
-        const FRIC: f32 = 0.98;
-        vel = vel.mult(FRIC);
+    pub fn is_asleep(&self, id: usize) -> bool {
+        self.flags[id] & Self::ASLEEP != 0
+    }
 
-        for force in forces {
-            vel = vel.add(force.get_force(self.pos));
+    /// Inverse mass as seen by constraints, fixed nodes can't be moved
+    pub fn weight(&self, id: usize) -> f32 {
+        if (self.flags[id] & Self::FIXED != 0) {
+            0.0
+        } else {
+            self.inv_mass[id]
         }
+    }
+
+    pub fn vel(&self, id: usize) -> Vec2 {
+        self.pos[id].sub(self.prev_pos[id])
+    }
 
-        self.prev_pos = self.pos;
-        //self.pos = self.pos.add(vel.mult(dt_norm));
-        self.pos = self.pos.add(vel);
+    pub fn set_vel(&mut self, id: usize, vel: Vec2) {
+        self.prev_pos[id] = self.pos[id].sub(vel);
     }
 }
 
+/// A snapshot of a single node, see `World::get_node`.
+#[derive(Debug, Clone, Copy)]
+pub struct RopeNode {
+    pub node_type: NodeType,
+    pub pos: Vec2,
+    pub vel: Vec2,
+    pub inv_mass: f32,
+    pub reaction: Vec2,
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct Rope {
     pub from: usize,
     pub to: usize,
-    length: f32,
+    pub(crate) length: f32,
     pub broken: bool,
+
+    /// Stretch past the rest length, as a fraction of it, at which the rope breaks.
+    pub break_strain: f32,
+
+    /// Width of the rope against colliders and other ropes, zero to only collide its end nodes.
+    pub thickness: f32,
+    /// Ropes in the same non-zero group pass through each other.
+    pub group: u32,
+    pub filter: CollisionFilter,
 }
 
 impl Rope {
+    /// Shortest rest length a rope can have, so its strain stays finite.
+    pub const MIN_LENGTH: f32 = 0.01;
+
     fn new(from: usize, to: usize, world: &World) -> Self {
         let length = world.get_node(from).pos.dist(world.get_node(to).pos);
+        Self::with_length(from, to, length)
+    }
+
+    fn with_length(from: usize, to: usize, length: f32) -> Self {
         Self {
             from,
             to,
-            length,
+            length: length.max(Self::MIN_LENGTH),
             broken: false,
+            break_strain: f32::INFINITY,
+            thickness: 0.0,
+            group: 0,
+            filter: CollisionFilter::default(),
         }
     }
-}
 
-pub struct Collider {}
+    pub fn length(&self) -> f32 {
+        self.length
+    }
+}
 
 pub trait Force {
     fn get_force(&self, rope_node_pos: Vec2) -> Vec2;
+
+    /// Add the force at each position onto `acc`.
+    fn accumulate(&self, pos: &[Vec2], acc: &mut [Vec2]) {
+        for (p, a) in pos.iter().zip(acc.iter_mut()) {
+            *a = a.add(self.get_force(*p));
+        }
+    }
 }
 
 pub struct ConstantForce {
@@ -230,6 +573,12 @@ impl Force for ConstantForce {
     fn get_force(&self, _: Vec2) -> Vec2 {
         self.force
     }
+
+    fn accumulate(&self, _: &[Vec2], acc: &mut [Vec2]) {
+        for a in acc {
+            *a = a.add(self.force);
+        }
+    }
 }
 
 pub struct InverseSquareForce {
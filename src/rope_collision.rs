@@ -0,0 +1,338 @@
+use crate::rope::*;
+
+/// Two thick ropes close enough that the solver might push one into the other.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RopePair {
+    pub a: usize,
+    pub b: usize,
+    /// Direction from `b` to `a` at the start of the tick, so ropes that pass right through
+    /// each other in one tick are still pushed back to the side they came from.
+    pub normal: Vec2,
+}
+
+impl RopePair {
+    fn new(a: usize, b: usize) -> Self {
+        Self {
+            a: a.min(b),
+            b: a.max(b),
+            normal: Vec2::default(),
+        }
+    }
+}
+
+impl World {
+    /// Ropes in the same non-zero group pass through each other, such as all the ropes of one structure.
+    pub fn set_rope_group(&mut self, id: usize, group: u32) {
+        self.ropes[id].group = group;
+        self.wake_rope(id);
+    }
+
+    /// Find pairs of ropes within a tick's travel of touching, after integration and before solving.
+    /// Ropes are bucketed into a grid sized to the average rope, then only ropes sharing a cell are compared.
+    pub(crate) fn find_rope_pairs(&mut self) {
+        // Extra room for the solver dragging ropes together
+        const MARGIN: f32 = 1.0;
+        // Ropes covering more cells than this skip the grid
+        const MAX_CELLS: i64 = 64;
+
+        self.rope_pairs.clear();
+        if (!self.rope_collision) {
+            return;
+        }
+
+        let nodes = &self.nodes;
+        let travel = |id: usize| nodes.pos[id].sub(nodes.prev_pos[id]).mag();
+
+        // Bounds of every rope that can collide, grown by its radius and how far its ends moved
+        let mut bounds = std::mem::take(&mut self.rope_bounds);
+        bounds.clear();
+        let mut total_extent = 0.0;
+        for (id, rope) in self.ropes.iter().enumerate() {
+            if (rope.broken || rope.thickness <= 0.0) {
+                continue;
+            }
+
+            let from = nodes.pos[rope.from];
+            let to = nodes.pos[rope.to];
+            let grow = rope.thickness * 0.5 + travel(rope.from).max(travel(rope.to)) + MARGIN;
+            let min = Vec2::new(from.x.min(to.x) - grow, from.y.min(to.y) - grow);
+            let max = Vec2::new(from.x.max(to.x) + grow, from.y.max(to.y) + grow);
+            total_extent += (max.x - min.x).max(max.y - min.y);
+            bounds.push((id, min, max));
+        }
+
+        if (bounds.len() < 2) {
+            self.rope_bounds = bounds;
+            return;
+        }
+
+        let cell_size = (total_extent / bounds.len() as f32).max(1.0);
+        let cell = |v: f32| (v / cell_size).floor() as i64;
+
+        let mut cells = std::mem::take(&mut self.rope_cells);
+        cells.clear();
+        let mut coarse = vec![];
+        for &(id, min, max) in &bounds {
+            let (x0, x1, y0, y1) = (cell(min.x), cell(max.x), cell(min.y), cell(max.y));
+            if ((x1 - x0 + 1) * (y1 - y0 + 1) > MAX_CELLS) {
+                // Very long ropes are checked against everything instead
+                coarse.push(id);
+                continue;
+            }
+
+            for x in x0..=x1 {
+                for y in y0..=y1 {
+                    let key = ((x as u32 as u64) << 32) | (y as u32 as u64);
+                    cells.push((key, id));
+                }
+            }
+        }
+        cells.sort_unstable();
+
+        let mut start = 0;
+        while (start < cells.len()) {
+            let key = cells[start].0;
+            let mut end = start;
+            while (end < cells.len() && cells[end].0 == key) {
+                end += 1;
+            }
+
+            for i in start..end {
+                for j in (i + 1)..end {
+                    let (a, b) = (cells[i].1, cells[j].1);
+                    if (self.ropes_can_collide(a, b)) {
+                        self.rope_pairs.push(RopePair::new(a, b));
+                    }
+                }
+            }
+
+            start = end;
+        }
+
+        for &long in &coarse {
+            for &(other, _, _) in &bounds {
+                if (other != long && self.ropes_can_collide(long, other)) {
+                    self.rope_pairs.push(RopePair::new(long, other));
+                }
+            }
+        }
+
+        self.rope_pairs.sort_unstable_by_key(|pair| (pair.a, pair.b));
+        self.rope_pairs.dedup_by_key(|pair| (pair.a, pair.b));
+
+        // Only keep pairs whose grown bounds overlap, and wake sleeping ropes something is about to hit
+        let bounds_of = |id: usize| {
+            let i = bounds.binary_search_by_key(&id, |&(bid, _, _)| bid).unwrap();
+            (bounds[i].1, bounds[i].2)
+        };
+        let weights = &self.weights;
+        let ropes = &self.ropes;
+        let prev_pos = &self.nodes.prev_pos;
+        let islands = &mut self.islands;
+        self.rope_pairs.retain_mut(|pair| {
+            let (a_min, a_max) = bounds_of(pair.a);
+            let (b_min, b_max) = bounds_of(pair.b);
+            if (a_max.x < b_min.x || b_max.x < a_min.x || a_max.y < b_min.y || b_max.y < a_min.y) {
+                return false;
+            }
+
+            let moving = |id: usize| weights[ropes[id].from] != 0.0 || weights[ropes[id].to] != 0.0;
+            for (hit, by) in [(pair.a, pair.b), (pair.b, pair.a)] {
+                if (!moving(hit) && moving(by)) {
+                    islands.wake_node(ropes[hit].from);
+                }
+            }
+
+            if (!moving(pair.a) && !moving(pair.b)) {
+                return false;
+            }
+
+            let (ra, rb) = (&ropes[pair.a], &ropes[pair.b]);
+            let (p1, q1, p2, q2) = (prev_pos[ra.from], prev_pos[ra.to], prev_pos[rb.from], prev_pos[rb.to]);
+            let (s, t) = closest_between_segments(p1, q1, p2, q2);
+            let delta = p1.add(q1.sub(p1).mult(s)).sub(p2.add(q2.sub(p2).mult(t)));
+            if (delta.mag2() > 0.0) {
+                pair.normal = delta.norm();
+            }
+            true
+        });
+
+        self.rope_bounds = bounds;
+        self.rope_cells = cells;
+    }
+
+    fn ropes_can_collide(&self, a: usize, b: usize) -> bool {
+        let ra = &self.ropes[a];
+        let rb = &self.ropes[b];
+        if ((ra.group != 0 && ra.group == rb.group) || !ra.filter.collides(&rb.filter)) {
+            return false;
+        }
+
+        ra.from != rb.from && ra.from != rb.to && ra.to != rb.from && ra.to != rb.to
+    }
+}
+
+/// Fractions along the segments `p1`-`q1` and `p2`-`q2` of their closest points.
+fn closest_between_segments(p1: Vec2, q1: Vec2, p2: Vec2, q2: Vec2) -> (f32, f32) {
+    const EPSILON: f32 = 1e-8;
+
+    let d1 = q1.sub(p1);
+    let d2 = q2.sub(p2);
+    let r = p1.sub(p2);
+    let a = d1.dot(d1);
+    let e = d2.dot(d2);
+    let f = d2.dot(r);
+
+    if (a <= EPSILON && e <= EPSILON) {
+        return (0.0, 0.0);
+    }
+    if (a <= EPSILON) {
+        return (0.0, (f / e).clamp(0.0, 1.0));
+    }
+
+    let c = d1.dot(r);
+    if (e <= EPSILON) {
+        return ((-c / a).clamp(0.0, 1.0), 0.0);
+    }
+
+    // Closest points on the infinite lines, clamped back onto the segments
+    let b = d1.dot(d2);
+    let denom = a * e - b * b;
+    let s = if (denom != 0.0) {
+        ((b * f - c * e) / denom).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+
+    let t = (b * s + f) / e;
+    if (t < 0.0) {
+        ((-c / a).clamp(0.0, 1.0), 0.0)
+    } else if (t > 1.0) {
+        (((b - c) / a).clamp(0.0, 1.0), 1.0)
+    } else {
+        (s, t)
+    }
+}
+
+/// Push two thick ropes apart at their closest points, moving each end by how close it is to
+/// that point. Returns how far they overlapped.
+pub(crate) fn solve_rope_pair(pair: &RopePair, ropes: &[Rope], pos: &mut [Vec2], weights: &[f32]) -> f32 {
+    let ra = &ropes[pair.a];
+    let rb = &ropes[pair.b];
+    if (ra.broken || rb.broken) {
+        return 0.0;
+    }
+
+    let (p1, q1, p2, q2) = (pos[ra.from], pos[ra.to], pos[rb.from], pos[rb.to]);
+    let (s, t) = closest_between_segments(p1, q1, p2, q2);
+    let c1 = p1.add(q1.sub(p1).mult(s));
+    let c2 = p2.add(q2.sub(p2).mult(t));
+
+    let reach = (ra.thickness + rb.thickness) * 0.5;
+    let delta = c1.sub(c2);
+    let normal = if (pair.normal.mag2() > 0.0) {
+        pair.normal
+    } else if (delta.mag2() > 0.0) {
+        delta.norm()
+    } else {
+        // Crossing exactly from the start, push out sideways from the first rope
+        let d = q1.sub(p1).norm();
+        Vec2::new(-d.y, d.x)
+    };
+
+    // Measured along the starting normal, so it goes negative once the ropes have crossed
+    let separation = delta.dot(normal);
+    if (separation >= reach || (delta.mag() >= reach && separation > 0.0)) {
+        return 0.0;
+    }
+
+    let ends = [
+        (ra.from, 1.0 - s, 1.0),
+        (ra.to, s, 1.0),
+        (rb.from, 1.0 - t, -1.0),
+        (rb.to, t, -1.0),
+    ];
+
+    let depth = reach - separation;
+    let denom: f32 = ends.iter().map(|&(id, share, _)| weights[id] * share * share).sum();
+    if (denom == 0.0) {
+        return depth;
+    }
+
+    let k = depth / denom;
+    for &(id, share, side) in &ends {
+        pos[id] = pos[id].add(normal.mult(side * k * weights[id] * share));
+    }
+
+    depth
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gap(p1: Vec2, q1: Vec2, p2: Vec2, q2: Vec2) -> f32 {
+        let (s, t) = closest_between_segments(p1, q1, p2, q2);
+        p1.add(q1.sub(p1).mult(s)).dist(p2.add(q2.sub(p2).mult(t)))
+    }
+
+    #[test]
+    fn crossing() {
+        let (s, t) = closest_between_segments(
+            Vec2::new(0.0, 0.0),
+            Vec2::new(10.0, 0.0),
+            Vec2::new(5.0, -5.0),
+            Vec2::new(5.0, 5.0),
+        );
+        assert!((s - 0.5).abs() < 1e-6);
+        assert!((t - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn past_the_ends() {
+        // The lines cross beyond both segments, so the nearest ends are used
+        let (s, t) = closest_between_segments(
+            Vec2::new(0.0, 0.0),
+            Vec2::new(1.0, 0.0),
+            Vec2::new(3.0, 1.0),
+            Vec2::new(3.0, 4.0),
+        );
+        assert_eq!((s, t), (1.0, 0.0));
+    }
+
+    #[test]
+    fn parallel_overlapping() {
+        let d = gap(Vec2::new(0.0, 0.0), Vec2::new(10.0, 0.0), Vec2::new(4.0, 2.0), Vec2::new(14.0, 2.0));
+        assert!((d - 2.0).abs() < 1e-5);
+
+        // Reversed direction
+        let d = gap(Vec2::new(0.0, 0.0), Vec2::new(10.0, 0.0), Vec2::new(14.0, 2.0), Vec2::new(4.0, 2.0));
+        assert!((d - 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn parallel_apart() {
+        // End to end on the same line
+        let (s, t) = closest_between_segments(
+            Vec2::new(0.0, 0.0),
+            Vec2::new(10.0, 0.0),
+            Vec2::new(13.0, 0.0),
+            Vec2::new(20.0, 0.0),
+        );
+        assert_eq!((s, t), (1.0, 0.0));
+    }
+
+    #[test]
+    fn points() {
+        let a = Vec2::new(1.0, 1.0);
+        assert_eq!(closest_between_segments(a, a, Vec2::new(3.0, 3.0), Vec2::new(3.0, 3.0)), (0.0, 0.0));
+        assert_eq!(
+            closest_between_segments(a, a, Vec2::new(0.0, 0.0), Vec2::new(4.0, 0.0)),
+            (0.0, 0.25)
+        );
+        assert_eq!(
+            closest_between_segments(Vec2::new(0.0, 0.0), Vec2::new(4.0, 0.0), a, a),
+            (0.25, 0.0)
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use crate::rope::*;
+
+/// A group of nodes pulled towards the best fit rigid placement of their rest shape,
+/// so a cell keeps its form however much the ropes through it would let it shear.
+/// Stops acting once any of `ropes` breaks.
+#[derive(Debug, Clone)]
+pub struct ShapeCluster {
+    pub nodes: Vec<usize>,
+    pub ropes: Vec<usize>,
+    /// Fraction of the way to the rigid placement each node moves per solver pass.
+    pub stiffness: f32,
+
+    // Where the nodes were when the cluster was made
+    rest: Vec<Vec2>,
+}
+
+impl World {
+    pub fn add_shape_cluster(&mut self, nodes: &[usize], ropes: &[usize], stiffness: f32) -> usize {
+        debug_assert!(nodes.len() >= 2);
+
+        let rest = nodes.iter().map(|&id| self.nodes.pos[id]).collect();
+        self.clusters.push(ShapeCluster {
+            nodes: nodes.to_vec(),
+            ropes: ropes.to_vec(),
+            stiffness: stiffness.clamp(0.0, 1.0),
+            rest,
+        });
+        self.mark_topology_dirty();
+
+        for &id in nodes {
+            self.islands.wake_node(id);
+        }
+        self.clusters.len() - 1
+    }
+
+    pub fn get_shape_cluster(&self, id: usize) -> &ShapeCluster {
+        &self.clusters[id]
+    }
+}
+
+impl ShapeCluster {
+    /// Move the cluster's free nodes towards their rigid placement, returning how far each one was from it.
+    /// Pinned nodes (zero weight) can't move so they decide the placement: with one the shape turns
+    /// about it, with two or more they set the rotation too.
+    pub(crate) fn solve(&self, ropes: &[Rope], pos: &mut [Vec2], weights: &[f32], mut on_error: impl FnMut(f32)) {
+        if (self.ropes.iter().any(|&rid| ropes[rid].broken)) {
+            return;
+        }
+
+        let pinned = self.nodes.iter().filter(|&&id| weights[id] == 0.0).count();
+        if (pinned == self.nodes.len()) {
+            return;
+        }
+
+        // Mass weighted centroids, or the plain centroid of the pinned nodes if there are any
+        let centroid_weight = |id: usize| {
+            if (pinned == 0) {
+                1.0 / weights[id]
+            } else if (weights[id] == 0.0) {
+                1.0
+            } else {
+                0.0
+            }
+        };
+
+        let mut total = 0.0;
+        let mut centre = Vec2::default();
+        let mut rest_centre = Vec2::default();
+        for (&id, &rest) in self.nodes.iter().zip(self.rest.iter()) {
+            let m = centroid_weight(id);
+            total += m;
+            centre = centre.add(pos[id].mult(m));
+            rest_centre = rest_centre.add(rest.mult(m));
+        }
+        centre = centre.mult(1.0 / total);
+        rest_centre = rest_centre.mult(1.0 / total);
+
+        // Best fit rotation from the weighted sums of dot and cross products of rest and current offsets
+        let mut dot = 0.0;
+        let mut cross = 0.0;
+        for (&id, &rest) in self.nodes.iter().zip(self.rest.iter()) {
+            let m = if (pinned >= 2) {
+                centroid_weight(id)
+            } else if (weights[id] == 0.0) {
+                1.0
+            } else {
+                1.0 / weights[id]
+            };
+
+            let r = rest.sub(rest_centre);
+            let p = pos[id].sub(centre);
+            dot += m * r.dot(p);
+            cross += m * (r.x * p.y - r.y * p.x);
+        }
+        let angle = cross.atan2(dot);
+
+        for (&id, &rest) in self.nodes.iter().zip(self.rest.iter()) {
+            if (weights[id] == 0.0) {
+                continue;
+            }
+
+            let goal = centre.add(rest.sub(rest_centre).rotate(angle));
+            let offset = goal.sub(pos[id]);
+            on_error(offset.mag());
+            pos[id] = pos[id].add(offset.mult(self.stiffness));
+        }
+    }
+}
@@ -0,0 +1,672 @@
+use std::collections::VecDeque;
+
+use rand::rngs::SmallRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+use crate::angles::{collect_angles, AngleConstraint};
+use crate::colliders::{solve_contact, solve_rope_contact, Collider, Contact, RopeContact};
+use crate::islands::Islands;
+use crate::pulleys::Pulley;
+use crate::rope_collision::{solve_rope_pair, RopePair};
+use crate::rope::*;
+use crate::shapes::ShapeCluster;
+use crate::tethers::{collect_tethers, Tether};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SolverMode {
+    /// Gauss-Seidel over ropes on the calling thread, in the solver's `ordering`.
+    Sequential,
+
+    /// Gauss-Seidel over colours, where no two ropes in a colour share a node,
+    /// with the ropes inside each colour solved in parallel.
+    Coloured,
+
+    /// Every rope solved against the same positions in parallel, with the corrections averaged per node.
+    /// Converges more slowly than the Gauss-Seidel modes so ropes come out stretchier.
+    Jacobi,
+}
+
+/// The order the sequential solver visits ropes in.
+/// Gauss-Seidel favours whatever it solves last, so a fixed order makes structures lean.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SolverOrdering {
+    /// Creation order every iteration.
+    Forward,
+
+    /// Creation order, then reversed on every other iteration.
+    Alternating,
+
+    /// A fresh random order each tick, reproducible from the seed.
+    Shuffled { seed: u64 },
+
+    /// Grouped by island, then outwards from each island's fixed nodes.
+    IslandLocal,
+}
+
+pub struct Solver {
+    pub mode: SolverMode,
+    pub ordering: SolverOrdering,
+
+    /// The most passes a tick will make.
+    pub iterations: usize,
+    /// Passes made before checking `tolerance`.
+    pub min_iterations: usize,
+    /// Stop once no rope is further than this from its rest length, zero to always run every pass.
+    pub tolerance: f32,
+
+    /// Passes made last tick.
+    pub last_iterations: usize,
+    /// Largest and root mean square rope length error measured in the last pass.
+    pub last_max_error: f32,
+    pub last_rms_error: f32,
+
+    pool: Option<ThreadPool>,
+    tick: u64,
+
+    // Unbroken ropes in awake islands, the only ones any mode visits
+    awake_ropes: Vec<usize>,
+    awake_rope_count: usize,
+    awake_dirty: bool,
+
+    colours: Vec<Vec<usize>>,
+    colour_used: Vec<u64>,
+    // Ropes that didn't fit in the colour mask, solved sequentially after the colours
+    uncoloured: Vec<usize>,
+    coloured_rope_count: usize,
+    colours_dirty: bool,
+
+    order: Vec<usize>,
+    ordered_rope_count: usize,
+    order_dirty: bool,
+
+    corrections: Vec<Option<Correction>>,
+    jacobi_delta: Vec<Vec2>,
+    jacobi_count: Vec<u32>,
+
+    tethers: Vec<Tether>,
+    angles: Vec<AngleConstraint>,
+}
+
+impl Default for Solver {
+    fn default() -> Self {
+        Self {
+            mode: SolverMode::Sequential,
+            ordering: SolverOrdering::Forward,
+            iterations: 8,
+            min_iterations: 1,
+            tolerance: 0.0,
+            last_iterations: 0,
+            last_max_error: 0.0,
+            last_rms_error: 0.0,
+            pool: None,
+            tick: 0,
+            awake_ropes: vec![],
+            awake_rope_count: 0,
+            awake_dirty: true,
+            colours: vec![],
+            colour_used: vec![],
+            uncoloured: vec![],
+            coloured_rope_count: 0,
+            colours_dirty: true,
+            order: vec![],
+            ordered_rope_count: 0,
+            order_dirty: true,
+            corrections: vec![],
+            jacobi_delta: vec![],
+            jacobi_count: vec![],
+            tethers: vec![],
+            angles: vec![],
+        }
+    }
+}
+
+impl Solver {
+    /// Call when ropes change or islands fall asleep or wake.
+    pub fn mark_dirty(&mut self) {
+        self.awake_dirty = true;
+        self.colours_dirty = true;
+        self.order_dirty = true;
+    }
+
+    /// Use a dedicated pool of `threads` threads, or rayon's global pool when zero.
+    /// Results only depend on the solver mode, not the thread count.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.pool = if (threads == 0) {
+            None
+        } else {
+            ThreadPoolBuilder::new().num_threads(threads).build().ok()
+        };
+    }
+
+    pub fn colour_count(&self) -> usize {
+        self.colours.len()
+    }
+
+    fn rebuild_awake_ropes(&mut self, ropes: &[Rope], islands: &Islands) {
+        self.awake_ropes.clear();
+        self.awake_ropes
+            .extend((0..ropes.len()).filter(|&rid| !ropes[rid].broken && !islands.node_asleep(ropes[rid].from)));
+
+        self.awake_rope_count = ropes.len();
+        self.awake_dirty = false;
+    }
+
+    // Greedy colouring, each rope takes the lowest colour not already used at either end
+    fn rebuild_colours(&mut self, ropes: &[Rope], node_count: usize) {
+        let mut used = std::mem::take(&mut self.colour_used);
+        used.clear();
+        used.resize(node_count, 0);
+        self.colours.clear();
+        self.uncoloured.clear();
+
+        for &rid in &self.awake_ropes {
+            let rope = &ropes[rid];
+            let colour = (!(used[rope.from] | used[rope.to])).trailing_zeros() as usize;
+            if (colour >= 64) {
+                self.uncoloured.push(rid);
+                continue;
+            }
+
+            if (colour >= self.colours.len()) {
+                self.colours.resize(colour + 1, vec![]);
+            }
+
+            self.colours[colour].push(rid);
+            used[rope.from] |= 1 << colour;
+            used[rope.to] |= 1 << colour;
+        }
+
+        self.colour_used = used;
+        self.coloured_rope_count = ropes.len();
+        self.colours_dirty = false;
+    }
+
+    fn shuffle_order(&mut self, seed: u64) {
+        self.order.clear();
+        self.order.extend_from_slice(&self.awake_ropes);
+
+        let mut rng = SmallRng::seed_from_u64(seed.wrapping_add(self.tick));
+        self.order.shuffle(&mut rng);
+
+        // The island local order has been overwritten
+        self.order_dirty = true;
+    }
+
+    // Breadth first from every fixed node, or from the root of islands without one,
+    // then sort ropes by island and by how far their nearer end is from the start
+    fn rebuild_island_order(&mut self, ropes: &[Rope], nodes: &Nodes, islands: &Islands) {
+        let node_count = nodes.len();
+
+        let mut neighbour_start = vec![0usize; node_count + 1];
+        for rope in ropes.iter().filter(|r| !r.broken) {
+            neighbour_start[rope.from + 1] += 1;
+            neighbour_start[rope.to + 1] += 1;
+        }
+        for i in 0..node_count {
+            neighbour_start[i + 1] += neighbour_start[i];
+        }
+
+        let mut neighbours = vec![0usize; neighbour_start[node_count]];
+        let mut fill = neighbour_start.clone();
+        for rope in ropes.iter().filter(|r| !r.broken) {
+            neighbours[fill[rope.from]] = rope.to;
+            fill[rope.from] += 1;
+            neighbours[fill[rope.to]] = rope.from;
+            fill[rope.to] += 1;
+        }
+
+        let mut depth = vec![usize::MAX; node_count];
+        let mut queue = VecDeque::new();
+        for (id, d) in depth.iter_mut().enumerate() {
+            let island = islands.island_of(id);
+            let is_start = if (islands.is_anchored(island)) {
+                nodes.node_type(id) == NodeType::Fixed
+            } else {
+                id == island
+            };
+
+            if (is_start) {
+                *d = 0;
+                queue.push_back(id);
+            }
+        }
+
+        while let Some(id) = queue.pop_front() {
+            for &n in &neighbours[neighbour_start[id]..neighbour_start[id + 1]] {
+                if (depth[n] == usize::MAX) {
+                    depth[n] = depth[id] + 1;
+                    queue.push_back(n);
+                }
+            }
+        }
+
+        self.order.clear();
+        self.order.extend_from_slice(&self.awake_ropes);
+        self.order.sort_by_key(|&rid| {
+            let rope = &ropes[rid];
+            (islands.island_of(rope.from), depth[rope.from].min(depth[rope.to]), rid)
+        });
+
+        self.ordered_rope_count = ropes.len();
+        self.order_dirty = false;
+    }
+}
+
+/// The moves that project a rope's end points back to its rest length,
+/// along with how far the rope was from that length.
+#[derive(Clone, Copy)]
+struct Correction {
+    from: Vec2,
+    to: Vec2,
+    error: f32,
+}
+
+// The rope length error over one pass
+#[derive(Clone, Copy, Default)]
+struct Residual {
+    max: f32,
+    sum_sq: f32,
+    count: usize,
+}
+
+impl Residual {
+    fn add(&mut self, error: f32) {
+        let error = error.abs();
+        self.max = self.max.max(error);
+        self.sum_sq += error * error;
+        self.count += 1;
+    }
+
+    fn merge(self, other: Residual) -> Residual {
+        Residual {
+            max: self.max.max(other.max),
+            sum_sq: self.sum_sq + other.sum_sq,
+            count: self.count + other.count,
+        }
+    }
+
+    fn rms(&self) -> f32 {
+        if (self.count == 0) {
+            0.0
+        } else {
+            (self.sum_sq / self.count as f32).sqrt()
+        }
+    }
+}
+
+/// `weights` are the nodes' inverse masses, zero for nodes that can't move this tick.
+fn rope_correction(rope: &Rope, pos: &[Vec2], weights: &[f32]) -> Option<Correction> {
+    pair_correction(rope, pos[rope.from], pos[rope.to], weights)
+}
+
+fn pair_correction(rope: &Rope, from_pos: Vec2, to_pos: Vec2, weights: &[f32]) -> Option<Correction> {
+    if (rope.broken) {
+        return None;
+    }
+
+    let w_from = weights[rope.from];
+    let w_to = weights[rope.to];
+    if (w_from == 0.0 && w_to == 0.0) {
+        // Nothing to do, both ends fixed or asleep
+        return None;
+    }
+
+    let delta = to_pos.sub(from_pos);
+    let dist = delta.mag();
+    if (dist == 0.0) {
+        return None;
+    }
+
+    // Pinned ropes only correct half the error per iteration, free ropes split it by inverse mass
+    let (share_from, share_to, share_total) = if (w_from == 0.0) {
+        (0.0, 1.0, 2.0)
+    } else if (w_to == 0.0) {
+        (1.0, 0.0, 2.0)
+    } else {
+        (w_from, w_to, w_from + w_to)
+    };
+
+    let error = dist - rope.length;
+    let k = error / (dist * share_total);
+    Some(Correction {
+        from: delta.mult(k * share_from),
+        to: delta.mult(-k * share_to),
+        error,
+    })
+}
+
+// Pinned ends record the pull of the rope as their reaction
+fn add_reaction(rope: &Rope, reaction: &mut [Vec2], weights: &[f32], correction: Correction) {
+    if (weights[rope.from] == 0.0) {
+        reaction[rope.from] = reaction[rope.from].sub(correction.to);
+    } else if (weights[rope.to] == 0.0) {
+        reaction[rope.to] = reaction[rope.to].sub(correction.from);
+    }
+}
+
+// A slice shared between solver threads that only touch disjoint elements of it
+#[derive(Clone, Copy)]
+struct SharedSlice<T>(*mut T);
+
+unsafe impl<T> Send for SharedSlice<T> {}
+unsafe impl<T> Sync for SharedSlice<T> {}
+
+impl<T: Copy> SharedSlice<T> {
+    unsafe fn get(&self, i: usize) -> T {
+        *self.0.add(i)
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn get_mut(&self, i: usize) -> &mut T {
+        &mut *self.0.add(i)
+    }
+}
+
+unsafe fn add_reaction_raw(rope: &Rope, reaction: SharedSlice<Vec2>, weights: &[f32], correction: Correction) {
+    if (weights[rope.from] == 0.0) {
+        let r = reaction.get_mut(rope.from);
+        *r = r.sub(correction.to);
+    } else if (weights[rope.to] == 0.0) {
+        let r = reaction.get_mut(rope.to);
+        *r = r.sub(correction.from);
+    }
+}
+
+fn solve_rope(rope: &Rope, pos: &mut [Vec2], reaction: &mut [Vec2], weights: &[f32], residual: &mut Residual) {
+    if let Some(correction) = rope_correction(rope, pos, weights) {
+        pos[rope.from] = pos[rope.from].add(correction.from);
+        pos[rope.to] = pos[rope.to].add(correction.to);
+        add_reaction(rope, reaction, weights, correction);
+        residual.add(correction.error);
+    }
+}
+
+// Tethers only pull, and pull the whole way back rather than sharing the error over iterations
+fn solve_tether(tether: &Tether, pos: &mut [Vec2], reaction: &mut [Vec2], weights: &[f32], residual: &mut Residual) {
+    let w_anchor = weights[tether.anchor];
+    let w_node = weights[tether.node];
+    if (w_anchor == 0.0 && w_node == 0.0) {
+        return;
+    }
+
+    let delta = pos[tether.node].sub(pos[tether.anchor]);
+    let dist = delta.mag();
+    if (dist <= tether.max_length) {
+        residual.add(0.0);
+        return;
+    }
+
+    let error = dist - tether.max_length;
+    residual.add(error);
+
+    let k = error / (dist * (w_anchor + w_node));
+    let anchor_move = delta.mult(k * w_anchor);
+    let node_move = delta.mult(-k * w_node);
+    pos[tether.anchor] = pos[tether.anchor].add(anchor_move);
+    pos[tether.node] = pos[tether.node].add(node_move);
+
+    if (w_anchor == 0.0) {
+        reaction[tether.anchor] = reaction[tether.anchor].sub(node_move);
+    } else if (w_node == 0.0) {
+        reaction[tether.node] = reaction[tether.node].sub(anchor_move);
+    }
+}
+
+impl World {
+    pub(crate) fn solve_ropes(&mut self) {
+        let solver = &mut self.solver;
+        if (solver.awake_dirty || solver.awake_rope_count != self.ropes.len()) {
+            solver.rebuild_awake_ropes(&self.ropes, &self.islands);
+        }
+        if (solver.mode != SolverMode::Sequential && (solver.colours_dirty || solver.coloured_rope_count != self.ropes.len())) {
+            solver.rebuild_colours(&self.ropes, self.nodes.len());
+        }
+
+        if (solver.mode == SolverMode::Sequential) {
+            match solver.ordering {
+                SolverOrdering::Shuffled { seed } => solver.shuffle_order(seed),
+                SolverOrdering::IslandLocal if (solver.order_dirty || solver.ordered_rope_count != self.ropes.len()) => {
+                    solver.rebuild_island_order(&self.ropes, &self.nodes, &self.islands);
+                }
+                _ => {}
+            }
+        }
+        solver.tick += 1;
+
+        let mut tethers = std::mem::take(&mut solver.tethers);
+        collect_tethers(&self.chains, &self.ropes, &self.nodes, &self.tethers, &mut tethers);
+        let mut angles = std::mem::take(&mut solver.angles);
+        collect_angles(&self.chains, &self.ropes, &self.angles, &mut angles);
+
+        let mut ropes = RopeSolve {
+            ropes: &self.ropes,
+            tethers: &tethers,
+            clusters: &self.clusters,
+            angles: &angles,
+            pulleys: &self.pulleys,
+            colliders: &self.colliders,
+            contacts: &self.contacts,
+            rope_contacts: &self.rope_contacts,
+            rope_pairs: &self.rope_pairs,
+            pos: &mut self.nodes.pos,
+            reaction: &mut self.nodes.reaction,
+            weights: &self.weights,
+        };
+
+        match solver.pool.take() {
+            Some(pool) => {
+                pool.install(|| ropes.solve(solver));
+                solver.pool = Some(pool);
+            }
+            None => ropes.solve(solver),
+        }
+
+        for pulley in &mut self.pulleys {
+            pulley.update_slide(&self.nodes.pos);
+        }
+
+        solver.tethers = tethers;
+        solver.angles = angles;
+    }
+}
+
+// The parts of the world the rope solver touches, split out so they can be sent to the thread pool
+struct RopeSolve<'a> {
+    ropes: &'a [Rope],
+    tethers: &'a [Tether],
+    clusters: &'a [ShapeCluster],
+    angles: &'a [AngleConstraint],
+    pulleys: &'a [Pulley],
+    colliders: &'a [Collider],
+    contacts: &'a [Contact],
+    rope_contacts: &'a [RopeContact],
+    rope_pairs: &'a [RopePair],
+    pos: &'a mut [Vec2],
+    reaction: &'a mut [Vec2],
+    weights: &'a [f32],
+}
+
+impl<'a> RopeSolve<'a> {
+    fn solve(&mut self, solver: &mut Solver) {
+        let mut iterations = 0;
+        let mut residual = Residual::default();
+
+        while (iterations < solver.iterations) {
+            residual = match solver.mode {
+                SolverMode::Sequential => self.sequential_pass(solver, iterations),
+                SolverMode::Coloured => self.coloured_pass(solver),
+                SolverMode::Jacobi => self.jacobi_pass(solver),
+            };
+            self.pulley_pass(&mut residual);
+            self.cluster_pass(&mut residual);
+            self.angle_pass();
+            self.tether_pass(&mut residual);
+            self.contact_pass(&mut residual);
+            iterations += 1;
+
+            // Only the max error decides when to stop, the summed error can round
+            // differently depending on how the thread pool splits the work
+            if (iterations >= solver.min_iterations && solver.tolerance > 0.0 && residual.max <= solver.tolerance) {
+                break;
+            }
+        }
+
+        solver.last_iterations = iterations;
+        solver.last_max_error = residual.max;
+        solver.last_rms_error = residual.rms();
+    }
+
+    fn pulley_pass(&mut self, residual: &mut Residual) {
+        for pulley in self.pulleys {
+            residual.add(pulley.solve(self.pos, self.reaction, self.weights));
+        }
+    }
+
+    fn cluster_pass(&mut self, residual: &mut Residual) {
+        for cluster in self.clusters {
+            cluster.solve(self.ropes, self.pos, self.weights, |error| residual.add(error));
+        }
+    }
+
+    // Angles are soft so they don't count towards the residual
+    fn angle_pass(&mut self) {
+        for angle in self.angles {
+            angle.solve(self.pos, self.weights);
+        }
+    }
+
+    fn tether_pass(&mut self, residual: &mut Residual) {
+        for tether in self.tethers {
+            solve_tether(tether, self.pos, self.reaction, self.weights, residual);
+        }
+    }
+
+    // Colliders go last so nothing is left pushed into them
+    fn contact_pass(&mut self, residual: &mut Residual) {
+        for pair in self.rope_pairs {
+            residual.add(solve_rope_pair(pair, self.ropes, self.pos, self.weights));
+        }
+        for contact in self.rope_contacts {
+            residual.add(solve_rope_contact(contact, self.ropes, self.colliders, self.pos, self.weights));
+        }
+        for contact in self.contacts {
+            residual.add(solve_contact(contact, self.colliders, self.pos));
+        }
+    }
+
+    fn sequential_pass(&mut self, solver: &Solver, iteration: usize) -> Residual {
+        let mut residual = Residual::default();
+        match solver.ordering {
+            SolverOrdering::Forward => {
+                for &rid in &solver.awake_ropes {
+                    solve_rope(&self.ropes[rid], self.pos, self.reaction, self.weights, &mut residual);
+                }
+            }
+            SolverOrdering::Alternating => {
+                if (iteration.is_multiple_of(2)) {
+                    for &rid in &solver.awake_ropes {
+                        solve_rope(&self.ropes[rid], self.pos, self.reaction, self.weights, &mut residual);
+                    }
+                } else {
+                    for &rid in solver.awake_ropes.iter().rev() {
+                        solve_rope(&self.ropes[rid], self.pos, self.reaction, self.weights, &mut residual);
+                    }
+                }
+            }
+            SolverOrdering::Shuffled { .. } | SolverOrdering::IslandLocal => {
+                for &rid in &solver.order {
+                    solve_rope(&self.ropes[rid], self.pos, self.reaction, self.weights, &mut residual);
+                }
+            }
+        }
+        residual
+    }
+
+    fn coloured_pass(&mut self, solver: &mut Solver) -> Residual {
+        const MIN_CHUNK: usize = 256;
+
+        let ropes = self.ropes;
+        let weights = self.weights;
+        let pos = SharedSlice(self.pos.as_mut_ptr());
+        let reaction = SharedSlice(self.reaction.as_mut_ptr());
+
+        let mut residual = Residual::default();
+        for colour in &solver.colours {
+            let colour_residual = colour
+                .par_iter()
+                .with_min_len(MIN_CHUNK)
+                .fold(Residual::default, |mut residual, &rid| {
+                    let rope = &ropes[rid];
+                    // No two ropes in a colour share a node, so every rope in the colour
+                    // reads and writes its own pair of nodes
+                    unsafe {
+                        let from = pos.get(rope.from);
+                        let to = pos.get(rope.to);
+                        if let Some(correction) = pair_correction(rope, from, to, weights) {
+                            *pos.get_mut(rope.from) = from.add(correction.from);
+                            *pos.get_mut(rope.to) = to.add(correction.to);
+                            add_reaction_raw(rope, reaction, weights, correction);
+                            residual.add(correction.error);
+                        }
+                    }
+                    residual
+                })
+                .reduce(Residual::default, Residual::merge);
+            residual = residual.merge(colour_residual);
+        }
+
+        for &rid in &solver.uncoloured {
+            solve_rope(&self.ropes[rid], self.pos, self.reaction, self.weights, &mut residual);
+        }
+        residual
+    }
+
+    fn jacobi_pass(&mut self, solver: &mut Solver) -> Residual {
+        const MIN_CHUNK: usize = 256;
+
+        let ropes = self.ropes;
+        let pos = &*self.pos;
+        let weights = self.weights;
+        solver
+            .awake_ropes
+            .par_iter()
+            .with_min_len(MIN_CHUNK)
+            .map(|&rid| rope_correction(&ropes[rid], pos, weights))
+            .collect_into_vec(&mut solver.corrections);
+
+        // Left zeroed between passes, only the ends of awake ropes are touched
+        let node_count = self.pos.len();
+        solver.jacobi_delta.resize(node_count, Vec2::default());
+        solver.jacobi_count.resize(node_count, 0);
+
+        // Accumulate in rope order so float rounding doesn't depend on the thread count
+        let mut residual = Residual::default();
+        for (&rid, correction) in solver.awake_ropes.iter().zip(solver.corrections.iter()) {
+            let rope = &ropes[rid];
+            if let Some(correction) = *correction {
+                solver.jacobi_delta[rope.from] = solver.jacobi_delta[rope.from].add(correction.from);
+                solver.jacobi_delta[rope.to] = solver.jacobi_delta[rope.to].add(correction.to);
+                solver.jacobi_count[rope.from] += 1;
+                solver.jacobi_count[rope.to] += 1;
+                add_reaction(rope, self.reaction, self.weights, correction);
+                residual.add(correction.error);
+            }
+        }
+
+        for &rid in &solver.awake_ropes {
+            let rope = &ropes[rid];
+            for node in [rope.from, rope.to] {
+                let count = solver.jacobi_count[node];
+                if (count > 0) {
+                    self.pos[node] = self.pos[node].add(solver.jacobi_delta[node].mult(1.0 / count as f32));
+                    solver.jacobi_delta[node] = Vec2::default();
+                    solver.jacobi_count[node] = 0;
+                }
+            }
+        }
+        residual
+    }
+}
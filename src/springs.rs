@@ -0,0 +1,68 @@
+use crate::rope::*;
+
+/// A Hookean spring and damper between two nodes, applied as a force before integration
+/// rather than projected like a `Rope`, so it can stretch and bounce.
+/// Stays stable while `stiffness` times the nodes' inverse mass is below about 1.
+#[derive(Debug, Clone, Copy)]
+pub struct Spring {
+    pub from: usize,
+    pub to: usize,
+    pub rest_length: f32,
+    pub stiffness: f32,
+    /// Resists the ends moving apart or together.
+    pub damping: f32,
+}
+
+impl World {
+    /// Uses the current distance between the nodes as the rest length.
+    pub fn add_spring(&mut self, from: usize, to: usize, stiffness: f32, damping: f32) -> usize {
+        debug_assert!(from != to);
+
+        self.springs.push(Spring {
+            from,
+            to,
+            rest_length: self.nodes.pos[from].dist(self.nodes.pos[to]),
+            stiffness: stiffness.max(0.0),
+            damping: damping.max(0.0),
+        });
+        self.mark_topology_dirty();
+        self.wake_spring(self.springs.len() - 1);
+        self.springs.len() - 1
+    }
+
+    pub fn get_spring(&self, id: usize) -> &Spring {
+        &self.springs[id]
+    }
+
+    pub fn set_spring_length(&mut self, id: usize, rest_length: f32) {
+        self.springs[id].rest_length = rest_length.max(0.0);
+        self.wake_spring(id);
+    }
+
+    fn wake_spring(&mut self, id: usize) {
+        let spring = self.springs[id];
+        self.islands.wake_node(spring.from);
+        self.islands.wake_node(spring.to);
+    }
+}
+
+/// Add the pull of every spring onto `acc`, velocities coming from the Verlet positions.
+pub(crate) fn accumulate_springs(springs: &[Spring], pos: &[Vec2], prev_pos: &[Vec2], acc: &mut [Vec2]) {
+    for spring in springs {
+        let delta = pos[spring.to].sub(pos[spring.from]);
+        let dist = delta.mag();
+        if (dist == 0.0) {
+            continue;
+        }
+
+        let dir = delta.mult(1.0 / dist);
+        let rel_vel = pos[spring.to]
+            .sub(prev_pos[spring.to])
+            .sub(pos[spring.from].sub(prev_pos[spring.from]));
+
+        let magnitude = spring.stiffness * (dist - spring.rest_length) + spring.damping * rel_vel.dot(dir);
+        let force = dir.mult(magnitude);
+        acc[spring.from] = acc[spring.from].add(force);
+        acc[spring.to] = acc[spring.to].sub(force);
+    }
+}
@@ -0,0 +1,85 @@
+use crate::chain::Chain;
+use crate::rope::*;
+
+/// A long range attachment, keeps `node` within `max_length` of `anchor` but never pushes it closer.
+/// Applied after every solver pass so long chains can't stretch past their rest length
+/// however few iterations they get.
+#[derive(Debug, Clone, Copy)]
+pub struct Tether {
+    pub anchor: usize,
+    pub node: usize,
+    pub max_length: f32,
+}
+
+impl World {
+    pub fn add_tether(&mut self, anchor: usize, node: usize, max_length: f32) -> usize {
+        debug_assert!(anchor < self.nodes.len());
+        debug_assert!(node < self.nodes.len());
+        debug_assert!(anchor != node);
+
+        self.tethers.push(Tether {
+            anchor,
+            node,
+            max_length: max_length.max(0.0),
+        });
+        self.mark_topology_dirty();
+        self.islands.wake_node(anchor);
+        self.islands.wake_node(node);
+        self.tethers.len() - 1
+    }
+
+    pub fn get_tether(&self, id: usize) -> &Tether {
+        &self.tethers[id]
+    }
+
+    pub fn set_tether_length(&mut self, id: usize, max_length: f32) {
+        let tether = &mut self.tethers[id];
+        tether.max_length = max_length.max(0.0);
+        let (anchor, node) = (tether.anchor, tether.node);
+        self.islands.wake_node(anchor);
+        self.islands.wake_node(node);
+    }
+}
+
+/// Every tether to solve this tick, the world's own plus the ones implied by chains with `lra` set.
+pub(crate) fn collect_tethers(chains: &[Chain], ropes: &[Rope], nodes: &Nodes, tethers: &[Tether], out: &mut Vec<Tether>) {
+    out.clear();
+    out.extend_from_slice(tethers);
+
+    for chain in chains.iter().filter(|c| c.lra) {
+        let first = chain.nodes[0];
+        if (nodes.node_type(first) == NodeType::Fixed) {
+            chain_tethers(first, chain.ropes.iter().zip(chain.nodes[1..].iter()), ropes, out);
+        }
+
+        let last = chain.nodes[chain.nodes.len() - 1];
+        if (nodes.node_type(last) == NodeType::Fixed) {
+            let walk = chain.ropes.iter().rev().zip(chain.nodes[..chain.nodes.len() - 1].iter().rev());
+            chain_tethers(last, walk, ropes, out);
+        }
+    }
+}
+
+// Tether each node along the walk to the anchor by the rest length of the chain up to it,
+// stopping at the first broken rope since everything past it has fallen off
+fn chain_tethers<'a>(
+    anchor: usize,
+    walk: impl Iterator<Item = (&'a usize, &'a usize)>,
+    ropes: &[Rope],
+    out: &mut Vec<Tether>,
+) {
+    let mut length = 0.0;
+    for (&rid, &node) in walk {
+        let rope = &ropes[rid];
+        if (rope.broken) {
+            break;
+        }
+
+        length += rope.length();
+        out.push(Tether {
+            anchor,
+            node,
+            max_length: length,
+        });
+    }
+}